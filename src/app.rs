@@ -1,34 +1,63 @@
 extern crate tetris_core as tc;
 
 use events::*;
+use layout::Layout;
+use input::{Controllers, Player};
+use render::SdlRenderer;
+use tr;
 
 use sdl2::EventPump;
-use sdl2::event::Event;
+use sdl2::controller::Button;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::{Point, Rect};
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::render::{Canvas, Texture, TextureCreator, WindowCanvas};
 use sdl2::video::{Window, WindowContext};
 
-pub const BORDER: u32 = 1;
-pub const RIGHT_PANEL: u32 = 9;
-pub const MAIN_WIDTH: u32 = BORDER + tc::COLUMNS as u32 + BORDER;
-pub const WINDOW_WIDTH: u32 = MAIN_WIDTH + RIGHT_PANEL + BORDER;
-pub const WINDOW_HEIGHT: u32 = BORDER + tc::ROWS as u32 + BORDER;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufWriter;
+
 pub const TARGET_RENDER_WIDTH: u32 = 440;
 pub const TARGET_RENDER_HEIGHT: u32 = 440;
 
 pub const WORKER_COUNT: u8 = 1;
 pub const TETRIS_COUNT: u32 = 4; // (4 as u32).pow(4) / WORKER_COUNT;
 
+pub const PLAYBACK_SEEK: u32 = 60;
+pub const PLAYBACK_MAX_SPEED: i32 = 8;
+
 pub struct App<'a> {
     canvas: Canvas<Window>,
+    // Boards are uploaded here, so it is kept the size of the last board
+    // painted and made again when that changes.
+    texture_creator: &'a TextureCreator<WindowContext>,
     texture: Texture<'a>,
+    texture_size: (u32, u32),
     events: EventPump,
     worker_count: u8,
     tetris_per_worker: u32,
     painter: Painter,
     op_event: Box<OpEvent + 'a>,
+    player: Option<tc::ReplayPlayer>,
+    playback_speed: i32,
+    boards: HashMap<(u8, u32), tc::DeltaDecoder>,
+    errors: HashMap<(u8, u32), tc::WorkerError>,
+    // The rules the workers play by, and each board's level from its last
+    // stats, for the soft drop speed.
+    ruleset: tc::Ruleset,
+    levels: HashMap<(u8, u32), u32>,
+    // Player 1 also moves with Tab, the number keys and the mouse.
+    players: Vec<Player>,
+    controllers: Controllers,
+    paused: bool,
+    // Records the board player 1 is on until G is pressed.
+    gif: Option<tr::GifRecorder<BufWriter<File>>>,
+    // Personal bests, written to the file whenever one is beaten.
+    bests: tc::Bests,
+    bests_path: Option<String>,
+    // Boards whose goal is reached and their run already kept.
+    finished: HashSet<(u8, u32)>,
 }
 
 impl<'a> App<'a> {
@@ -38,239 +67,575 @@ impl<'a> App<'a> {
         texture_creator: &'a TextureCreator<WindowContext>,
         worker_count: u8,
         tetris_per_worker: u32,
+        codec: tc::Codec,
+        preset: tc::Preset,
+        goal: tc::Goal,
+        players: Vec<Player>,
+        controllers: Controllers,
     ) -> App {
-        let mut op_event = EventMgr::new();
+        let painter = Painter::new(
+            worker_count,
+            tetris_per_worker,
+            canvas.output_size().unwrap(),
+        );
+
+        let mut op_event = EventMgr::new(codec);
         op_event.create(worker_count);
-        op_event.init(tetris_per_worker);
+        op_event.init(tetris_per_worker, preset, goal);
 
         App {
             canvas: canvas,
-            texture: texture_creator
-                .create_texture_target(None, WINDOW_WIDTH, WINDOW_HEIGHT)
-                .unwrap(),
+            texture_creator: texture_creator,
+            texture: board_texture(texture_creator, (tr::BOARD_WIDTH, tr::BOARD_HEIGHT)),
+            texture_size: (tr::BOARD_WIDTH, tr::BOARD_HEIGHT),
             events: events,
             worker_count: worker_count,
             tetris_per_worker: tetris_per_worker,
-            painter: Painter::new(worker_count as u32 * tetris_per_worker as u32),
+            painter: painter,
             op_event: Box::new(op_event),
+            player: None,
+            playback_speed: 1,
+            boards: HashMap::new(),
+            errors: HashMap::new(),
+            ruleset: tc::Ruleset::preset(preset),
+            levels: HashMap::new(),
+            players: players,
+            controllers: controllers,
+            paused: false,
+            gif: None,
+            bests: tc::Bests::default(),
+            bests_path: None,
+            finished: HashSet::new(),
         }
     }
 
+    //
+    // Plays a recorded game instead of running workers.
+    //
+    pub fn playback(
+        canvas: WindowCanvas,
+        events: EventPump,
+        texture_creator: &'a TextureCreator<WindowContext>,
+        replay: tc::Replay,
+    ) -> App {
+        let mut app = App::new(
+            canvas,
+            events,
+            texture_creator,
+            0,
+            0,
+            tc::Codec::Binary,
+            tc::Preset::Classic,
+            tc::Goal::Endless,
+            vec![],
+            Controllers::none(),
+        );
+        app.player = Some(tc::ReplayPlayer::new(replay).expect("Can not play the replay file!"));
+        app.painter = Painter::new(1, 1, app.canvas.output_size().unwrap());
+        app
+    }
+
+    pub fn record(&mut self, gif: tr::GifRecorder<BufWriter<File>>) {
+        self.gif = Some(gif);
+    }
+
+    //
+    // Keeps personal bests in `path`, read now if the file is there.
+    //
+    pub fn keep_bests(&mut self, path: String) {
+        if let Ok(mut file) = File::open(&path) {
+            match tc::Bests::read_from(&mut file) {
+                Ok(bests) => self.bests = bests,
+                Err(e) => println!("[main] bests not read: {}", e),
+            }
+        }
+        self.bests_path = Some(path);
+    }
+
+    //
+    // The first message of a board that reached its goal ends its run: it
+    // is printed and, when it beats the best for the goal, written down.
+    //
+    fn finish_run(&mut self, message: &tc::Msg) {
+        let board = (message.event.worker_id(), message.event.tetris_id());
+        let record = match (message.mode.as_ref(), message.stats.as_ref()) {
+            (Some(mode), Some(stats)) => mode.record(stats),
+            _ => None,
+        };
+        let record = match record {
+            Some(record) if !self.finished.contains(&board) => record,
+            _ => return,
+        };
+        self.finished.insert(board);
+
+        println!(
+            "[main] board {:?}: {} lines in {}, {} pieces, {} finesse faults",
+            board,
+            record.goal.lines(),
+            record.time(),
+            record.pieces,
+            record.faults
+        );
+        if !self.bests.add(record) {
+            return;
+        }
+        println!("[main] personal best");
+        if let Some(ref path) = self.bests_path {
+            let written = File::create(path)
+                .map_err(|e| e.to_string())
+                .and_then(|mut file| self.bests.write_to(&mut file).map_err(|e| e.to_string()));
+            if let Err(e) = written {
+                println!("[main] bests not written: {}", e);
+            }
+        }
+    }
+
+    fn finish_recording(&mut self) {
+        if let Some(gif) = self.gif.take() {
+            match gif.finish() {
+                Ok(_) => println!("[main] gif written"),
+                Err(e) => println!("[main] gif error: {}", e),
+            }
+        }
+    }
+
+    fn board_count(&self) -> u32 {
+        self.worker_count as u32 * self.tetris_per_worker
+    }
+
+    fn board_of(&self, index: u32) -> (u8, u32) {
+        (
+            (index / self.tetris_per_worker) as u8,
+            index % self.tetris_per_worker,
+        )
+    }
+
+    // Frames per row of a board at its last known level.
+    fn gravity_of(&self, worker_index: u8, tetris_index: u32) -> u32 {
+        let level = self.levels.get(&(worker_index, tetris_index)).cloned().unwrap_or(1);
+        self.ruleset.gravity(level)
+    }
+
+    fn fit_texture(&mut self, message: &tc::Msg) {
+        let size = tr::message_size(message);
+        if size != self.texture_size {
+            self.texture = board_texture(self.texture_creator, size);
+            self.texture_size = size;
+        }
+    }
+
+    fn set_focus(&mut self, index: u32) {
+        if index < self.board_count() {
+            if let Some(player) = self.players.first_mut() {
+                player.board = index;
+            }
+        }
+    }
+
+    //
+    // Every player's keys and controller buttons go to their own board, the
+    // boards nobody plays keep falling on their own. Tab, the number keys and
+    // mouse clicks move player 1 to another board.
+    //
+    // Keys are tracked as held or released and repeat with the player's
+    // DAS/ARR settings once per frame, so the OS key repeat is ignored.
+    //
     fn handle_events(&mut self) {
-        let events: Vec<tc::BlockEvent> = self.events
-            .poll_iter()
-            .map(|event| match event {
-                Event::KeyDown { keycode: Some(Keycode::Up), .. } => tc::BlockEvent::Rotate,
-                Event::KeyDown { keycode: Some(Keycode::Left), .. } => tc::BlockEvent::Left,
-                Event::KeyDown { keycode: Some(Keycode::Right), .. } => tc::BlockEvent::Right,
-                Event::KeyDown { keycode: Some(Keycode::Down), .. } => tc::BlockEvent::Down,
-                Event::KeyDown { keycode: Some(Keycode::Space), .. } => tc::BlockEvent::Drop,
-                _ => tc::BlockEvent::None,
-            })
-            .filter(|e| *e != tc::BlockEvent::None)
-            .collect();
+        let sdl_events: Vec<Event> = self.events.poll_iter().collect();
 
-        for worker_index in 0..self.worker_count {
-            for tetris_index in 0..self.tetris_per_worker {
-                self.op_event.send_app_event(tc::AppEvent::User(
-                    worker_index,
-                    tetris_index,
-                    Some(events.clone()),
-                ));
+        for event in sdl_events {
+            match event {
+                Event::KeyDown { keycode: Some(Keycode::P), repeat: false, .. } |
+                Event::ControllerButtonDown { button: Button::Start, .. } => {
+                    self.paused = !self.paused;
+                }
+                Event::KeyDown { keycode: Some(Keycode::G), repeat: false, .. } => {
+                    self.finish_recording();
+                }
+                Event::KeyDown { keycode: Some(Keycode::Tab), .. } => {
+                    if let Some(board) = self.players.first().map(|player| player.board) {
+                        let next = (board + 1) % self.board_count().max(1);
+                        self.set_focus(next);
+                    }
+                }
+                Event::KeyDown { keycode: Some(keycode), repeat: false, .. } => {
+                    if let Some(index) = number_key(keycode) {
+                        self.set_focus(index);
+                    }
+                    for player in self.players.iter_mut() {
+                        player.key_down(keycode);
+                    }
+                }
+                Event::KeyUp { keycode: Some(keycode), .. } => {
+                    for player in self.players.iter_mut() {
+                        player.key_up(keycode);
+                    }
+                }
+                Event::ControllerDeviceAdded { which, .. } => {
+                    let board_count = self.board_count();
+                    self.controllers.added(which as u32, &mut self.players, board_count);
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    self.controllers.removed(which, &mut self.players);
+                }
+                Event::ControllerButtonDown { which, button, .. } => {
+                    for player in self.players.iter_mut().filter(|p| p.controller == Some(which)) {
+                        player.button_down(button);
+                    }
+                }
+                Event::ControllerButtonUp { which, button, .. } => {
+                    for player in self.players.iter_mut().filter(|p| p.controller == Some(which)) {
+                        player.button_up(button);
+                    }
+                }
+                Event::Window { win_event: WindowEvent::SizeChanged(..), .. } => self.resize(),
+                Event::Window { win_event: WindowEvent::FocusLost, .. } => {
+                    for player in self.players.iter_mut() {
+                        player.repeat.release_all();
+                    }
+                }
+                Event::MouseButtonDown { x, y, .. } => {
+                    if let Some(index) = self.painter.board_at(x, y) {
+                        self.set_focus(index);
+                    }
+                }
+                _ => (),
             }
         }
 
+        // The frame's input goes out as one message per worker, with the
+        // players on one board merged in player order.
+        let board_count = self.board_count();
+        let mut inputs: Vec<Vec<(u32, Vec<tc::BlockEvent>)>> = vec![Vec::new(); self.worker_count as usize];
+        for player_index in 0..self.players.len() {
+            let board = self.players[player_index].board;
+            let (worker_index, tetris_index) = self.board_of(board);
+            let gravity = self.gravity_of(worker_index, tetris_index);
+            let columns = self.painter.columns(self.painter.index(worker_index, tetris_index));
+            let events = self.players[player_index].repeat.tick(gravity, columns);
+            if events.is_empty() || board >= board_count || self.paused {
+                continue;
+            }
+
+            let ref mut worker_inputs = inputs[worker_index as usize];
+            match worker_inputs.iter().position(|input| input.0 == tetris_index) {
+                Some(index) => worker_inputs[index].1.extend(events),
+                None => worker_inputs.push((tetris_index, events)),
+            }
+        }
+
+        for (worker_index, inputs) in inputs.into_iter().enumerate() {
+            if !inputs.is_empty() {
+                self.op_event.send_app_event(tc::AppEvent::UserEach(worker_index as u8, inputs));
+            }
+        }
     }
 
-    fn check_gravity(&mut self) {
+    //
+    // Boards are only painted when their worker answers, so clear the old
+    // layout away and ask every board for its full state.
+    //
+    fn resize(&mut self) {
+        self.painter.resize(self.canvas.output_size().unwrap());
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+
         for worker_index in 0..self.worker_count {
             for tetris_index in 0..self.tetris_per_worker {
-                self.op_event.send_app_event(
-                    tc::AppEvent::Tick(worker_index, tetris_index),
-                );
+                self.op_event.send_app_event(tc::AppEvent::Resync(worker_index, tetris_index));
             }
         }
     }
 
+    fn check_gravity(&mut self) {
+        for worker_index in 0..self.worker_count {
+            self.op_event.send_app_event(tc::AppEvent::TickAll(worker_index));
+        }
+    }
+
+    //
+    // Workers send the full state of a board once and deltas after that, so
+    // every message goes through the board's decoder before it is painted.
+    //
     fn handle_messages(&mut self) {
         for message in self.op_event.received() {
-            self.painter.paint(
-                &message,
-                &mut self.canvas,
-                &mut self.texture,
-            );
+            let worker_index = message.event.worker_id();
+            let tetris_index = message.event.tetris_id();
+
+            match message.event {
+                tc::AppEvent::InitWorker(..) => continue,
+                tc::AppEvent::Error(_, error, ref event) => {
+                    self.worker_error(worker_index, error, event);
+                    continue;
+                }
+                _ => (),
+            }
+
+            let decoder = self.boards
+                .entry((worker_index, tetris_index))
+                .or_insert_with(tc::DeltaDecoder::new);
+
+            match decoder.apply(message) {
+                Ok(message) => {
+                    self.errors.remove(&(worker_index, tetris_index));
+                    if let Some(ref stats) = message.stats {
+                        self.levels.insert((worker_index, tetris_index), stats.level());
+                    }
+                    self.record_message(&message);
+                    self.finish_run(&message);
+                    self.fit_texture(&message);
+                    let mut renderer = SdlRenderer::new(&mut self.canvas, &mut self.texture);
+                    self.painter.paint(&message, &mut renderer)
+                }
+                Err(tc::DeltaError::Resync) => {
+                    self.op_event.send_app_event(
+                        tc::AppEvent::Resync(worker_index, tetris_index),
+                    )
+                }
+                Err(tc::DeltaError::Waiting) => (),
+            }
+        }
+    }
+
+    fn record_message(&mut self, message: &tc::Msg) {
+        let board = match self.players.first() {
+            Some(player) if player.board < self.board_count() => self.board_of(player.board),
+            _ => return,
+        };
+        if board != (message.event.worker_id(), message.event.tetris_id()) {
+            return;
+        }
+
+        let failed = match self.gif {
+            Some(ref mut gif) => gif.frame(message).is_err(),
+            None => false,
+        };
+        if failed {
+            println!("[main] gif error, recording stopped");
+            self.gif = None;
+        }
+    }
+
+    //
+    // Marks the boards the failed event was meant for. They stay outlined
+    // until the worker sends them a good message again.
+    //
+    fn worker_error(
+        &mut self,
+        worker_index: u8,
+        error: tc::WorkerError,
+        event: &Option<Box<tc::AppEvent>>,
+    ) {
+        println!("[main] worker {}: {} ({:?})", worker_index, error, event);
+
+        let tetris_indexes = match *event {
+            Some(ref event) => match **event {
+                tc::AppEvent::InitTetris(_, tetris_index, _, _) |
+                tc::AppEvent::Tick(_, tetris_index) |
+                tc::AppEvent::User(_, tetris_index, _) |
+                tc::AppEvent::Resync(_, tetris_index) => vec![tetris_index],
+                tc::AppEvent::UserEach(_, ref inputs) => inputs.iter().map(|input| input.0).collect(),
+                _ => (0..self.tetris_per_worker).collect(),
+            },
+            None => (0..self.tetris_per_worker).collect(),
+        };
+
+        for tetris_index in tetris_indexes {
+            self.errors.insert((worker_index, tetris_index), error);
+        }
+    }
+
+    fn handle_playback(&mut self) {
+        let player = match self.player {
+            Some(ref mut player) => player,
+            None => return,
+        };
+
+        for event in self.events.poll_iter() {
+            match event {
+                Event::KeyDown { keycode: Some(Keycode::Space), .. } => {
+                    self.playback_speed = if self.playback_speed == 0 { 1 } else { 0 };
+                }
+                Event::KeyDown { keycode: Some(Keycode::Up), .. } => {
+                    self.playback_speed = (self.playback_speed + 1).min(PLAYBACK_MAX_SPEED);
+                }
+                Event::KeyDown { keycode: Some(Keycode::Down), .. } => {
+                    self.playback_speed = (self.playback_speed - 1).max(-PLAYBACK_MAX_SPEED);
+                }
+                Event::KeyDown { keycode: Some(Keycode::Right), .. } => {
+                    player.fast_forward(PLAYBACK_SEEK)
+                }
+                Event::KeyDown { keycode: Some(Keycode::Left), .. } => player.rewind(PLAYBACK_SEEK),
+                Event::KeyDown { keycode: Some(Keycode::Home), .. } => player.seek(0),
+                Event::Window { win_event: WindowEvent::SizeChanged(..), .. } => {
+                    self.painter.resize(self.canvas.output_size().unwrap());
+                    self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+                    self.canvas.clear();
+                }
+                _ => (),
+            }
+        }
+
+        if self.playback_speed > 0 {
+            player.fast_forward(self.playback_speed as u32);
+        } else if self.playback_speed < 0 {
+            player.rewind(self.playback_speed.abs() as u32);
+        }
+
+        let message = tr::state_message(player.tetris());
+        self.fit_texture(&message);
+        let mut renderer = SdlRenderer::new(&mut self.canvas, &mut self.texture);
+        self.painter.paint(&message, &mut renderer);
+
+        if self.playback_speed == 0 {
+            self.painter.paint_paused(&mut renderer);
         }
     }
 
     pub fn run(&mut self) {
-        self.check_gravity();
+        if self.player.is_some() {
+            self.handle_playback();
+            return;
+        }
+
+        if !self.paused {
+            self.check_gravity();
+        }
         self.handle_events();
         self.handle_messages();
+
+        let focus: Vec<(usize, (u8, u32))> = self.players
+            .iter()
+            .enumerate()
+            .filter(|&(_, player)| player.board < self.board_count())
+            .map(|(player_index, player)| (player_index, self.board_of(player.board)))
+            .collect();
+
+        let mut renderer = SdlRenderer::new(&mut self.canvas, &mut self.texture);
+        for (player_index, (worker_index, tetris_index)) in focus {
+            self.painter.paint_focus(player_index, worker_index, tetris_index, &mut renderer);
+        }
+        for &(worker_index, tetris_index) in self.errors.keys() {
+            self.painter.paint_error(worker_index, tetris_index, &mut renderer);
+        }
+        if self.paused {
+            self.painter.paint_paused(&mut renderer);
+        }
     }
 }
 
+fn board_texture(texture_creator: &TextureCreator<WindowContext>, (width, height): (u32, u32)) -> Texture {
+    texture_creator
+        .create_texture_streaming(PixelFormatEnum::ABGR8888, width, height)
+        .unwrap()
+}
+
+// 1 to 9 select the first nine boards.
+fn number_key(keycode: Keycode) -> Option<u32> {
+    let keys = [
+        Keycode::Num1,
+        Keycode::Num2,
+        Keycode::Num3,
+        Keycode::Num4,
+        Keycode::Num5,
+        Keycode::Num6,
+        Keycode::Num7,
+        Keycode::Num8,
+        Keycode::Num9,
+    ];
+    keys.iter().position(|key| *key == keycode).map(|index| index as u32)
+}
 
 struct Painter {
-    starts: Vec<tc::Point>,
-    width: u32,
-    height: u32,
-    scale: i32,
-    tetris_count: u32,
+    layout: Layout,
+    board_count: u32,
+    tetris_per_worker: u32,
+    // Boards are painted here one pixel per cell, then scaled into the
+    // layout.
+    board: tr::Framebuffer,
+    // Grid width of each board's last message, for the overlays.
+    columns: HashMap<usize, usize>,
 }
 
 impl Painter {
-    fn new(tetris: u32) -> Painter {
-        let tetris_count: u32 = WORKER_COUNT as u32 * TETRIS_COUNT;
-        let room_count = 2_u32.pow((tetris_count as f32).log(4.0) as u32);
-        let width = TARGET_RENDER_WIDTH / room_count;
-        let height = TARGET_RENDER_HEIGHT / room_count;
-        let scale = width / WINDOW_WIDTH;
-
-        println!(
-            "width: {} heigth:{}, room_count: {}, scale: {}",
-            width,
-            height,
-            room_count,
-            scale,
-        );
-
-        let mut w = 0;
-        let mut h = 0;
-        let mut starts: Vec<tc::Point> = Vec::new();
-
-        for _ in 0..room_count {
-            for _ in 0..room_count {
-                starts.push(tc::Point::new(w as i32, h as i32));
-                w += width;
-            }
-            w = 0;
-            h += height;
-        }
-
+    fn new(worker_count: u8, tetris_per_worker: u32, (width, height): (u32, u32)) -> Painter {
+        let board_count = worker_count as u32 * tetris_per_worker;
         Painter {
-            starts: starts,
-            width: width,
-            height: height,
-            scale: scale as i32,
-            tetris_count: tetris_count,
+            layout: Layout::new(board_count, tr::BOARD_WIDTH, tr::BOARD_HEIGHT, width, height),
+            board_count: board_count,
+            tetris_per_worker: tetris_per_worker,
+            board: tr::Framebuffer::new(tr::BOARD_WIDTH, tr::BOARD_HEIGHT),
+            columns: HashMap::new(),
         }
     }
 
-    fn _as_point(&self, x: i32, y: i32) -> Point {
-        Point::new(x + BORDER as i32, y + BORDER as i32)
+    // Re-flows the boards into a window of the new size.
+    fn resize(&mut self, (width, height): (u32, u32)) {
+        self.layout = Layout::new(self.board_count, tr::BOARD_WIDTH, tr::BOARD_HEIGHT, width, height);
     }
 
-    fn paint_border(&self, canvas: &mut Canvas<Window>) {
-        canvas.set_draw_color(Color::RGB(25, 25, 25));
-        canvas
-            .fill_rect(Rect::new(0, 0, MAIN_WIDTH, WINDOW_HEIGHT))
-            .unwrap();
+    fn index(&self, worker_index: u8, tetris_index: u32) -> usize {
+        (worker_index as u32 * self.tetris_per_worker + tetris_index) as usize
     }
 
-    fn paint_main(&self, message: &tc::Msg, canvas: &mut Canvas<Window>) {
-        canvas.set_draw_color(Color::RGB(75, 75, 75));
-        canvas
-            .fill_rect(Rect::new(1, 1, tc::COLUMNS as u32, tc::ROWS as u32))
-            .unwrap();
-
-        if let Some(ref block) = message.block {
-            let &(r, g, b) = block.color_ref();
-            let points: Vec<Point> = block
-                .points_ref()
-                .iter()
-                .filter(|point| point.y() >= 0)
-                .map(|point| self._as_point(point.x(), point.y()))
-                .collect();
-
-            canvas.set_draw_color(Color::RGB(r, g, b));
-            canvas.draw_points(points.as_slice()).unwrap();
-        }
+    // Grid width of the board's last message.
+    fn columns(&self, index: usize) -> usize {
+        self.columns.get(&index).cloned().unwrap_or(tc::COLUMNS)
     }
 
-    fn paint_scoreboard(&self, message: &tc::Msg, canvas: &mut Canvas<Window>) {
-        if let Some(ref block) = message.block {
-            if let &Some(ref next) = block.next_ref() {
-                let &(r, g, b) = next.color_ref();
-                let points: Vec<Point> = next.points_ref()
-                    .iter()
-                    .map(|point| {
-                        Point::new(point.x() + 3 + MAIN_WIDTH as i32, point.y() + 2)
-                    })
-                    .collect();
-
-                canvas.set_draw_color(Color::RGB(38, 38, 38));
-                canvas
-                    .fill_rect(Rect::new(
-                        MAIN_WIDTH as i32,
-                        0,
-                        WINDOW_WIDTH - MAIN_WIDTH,
-                        WINDOW_HEIGHT,
-                    ))
-                    .unwrap();
-
-                canvas.set_draw_color(Color::RGB(r, g, b));
-                canvas.draw_points(points.as_slice()).unwrap();
-            }
-        }
+    // Index of the board drawn at window position (x, y).
+    fn board_at(&self, x: i32, y: i32) -> Option<u32> {
+        self.layout.index_at(x, y).map(|index| index as u32)
     }
 
-    fn paint_grid(&self, message: &tc::Msg, canvas: &mut Canvas<Window>) {
+    fn outline<R: tr::Renderer>(&self, worker_index: u8, tetris_index: u32, color: tr::Rgb, r: &mut R) {
+        if let Some(rect) = self.layout.rect(self.index(worker_index, tetris_index)) {
+            r.draw_rect(rect, color);
+        }
+    }
 
-        if let Some(ref grid) = message.grid {
-            let data = grid.get_data();
-            for r_index in 0..data.len() {
-                for c_index in 0..data[r_index].len() {
-                    let piece = data[r_index][c_index];
-                    if piece == 0 {
-                        continue;
-                    }
+    fn paint_focus<R: tr::Renderer>(
+        &self,
+        player_index: usize,
+        worker_index: u8,
+        tetris_index: u32,
+        r: &mut R,
+    ) {
+        let colors = [
+            (230, 230, 120),
+            (120, 200, 230),
+            (140, 230, 120),
+            (230, 140, 220),
+        ];
+        let color = colors[player_index % colors.len()];
+        self.outline(worker_index, tetris_index, color, r);
+    }
 
-                    let (r, g, b) = if let Some(ref scheme) = message.scheme {
-                        scheme.color(&tc::BlockType::new(piece))
-                    } else {
-                        (255, 255, 255)
-                    };
+    fn paint_error<R: tr::Renderer>(&self, worker_index: u8, tetris_index: u32, r: &mut R) {
+        self.outline(worker_index, tetris_index, (200, 40, 40), r);
+    }
 
-                    canvas.set_draw_color(Color::RGB(r, g, b));
-                    canvas
-                        .draw_point(self._as_point(c_index as i32, r_index as i32))
-                        .unwrap();
-                }
+    fn paint_paused<R: tr::Renderer>(&self, r: &mut R) {
+        for index in 0..self.board_count.max(1) {
+            if let Some(rect) = self.layout.rect(index as usize) {
+                tr::paint_overlay(r, "PAUSED", rect, self.columns(index as usize));
             }
         }
     }
 
-    fn paint(&self, message: &tc::Msg, canvas: &mut Canvas<Window>, texture: &mut Texture) {
-        canvas
-            .with_texture_canvas(texture, |texture_canvas| {
-                texture_canvas.set_draw_color(Color::RGB(38, 38, 38));
-                texture_canvas.clear();
-
-                self.paint_border(texture_canvas);
-                self.paint_main(message, texture_canvas);
-                self.paint_grid(message, texture_canvas);
-                self.paint_scoreboard(message, texture_canvas);
-            })
-            .unwrap();
-
+    fn paint<R: tr::Renderer>(&mut self, message: &tc::Msg, r: &mut R) {
         match message.event {
-            tc::AppEvent::InitTetris(worker_index, tetris_index) |
+            tc::AppEvent::InitTetris(worker_index, tetris_index, _, _) |
             tc::AppEvent::Tick(worker_index, tetris_index) |
-            tc::AppEvent::User(worker_index, tetris_index, _) => {
-                let index = (worker_index as u32 * TETRIS_COUNT + tetris_index) as usize;
-                let start = &self.starts[index];
-                canvas
-                    .copy(
-                        texture,
-                        Some(Rect::new(0, 0, WINDOW_WIDTH, WINDOW_HEIGHT)),
-                        Some(Rect::new(
-                            start.x(),
-                            start.y(),
-                            WINDOW_WIDTH * self.scale as u32,
-                            WINDOW_HEIGHT * self.scale as u32,
-                        )),
-                    )
-                    .unwrap();
+            tc::AppEvent::User(worker_index, tetris_index, _) |
+            tc::AppEvent::Resync(worker_index, tetris_index) => {
+                let index = self.index(worker_index, tetris_index);
+                if let Some(ref grid) = message.grid {
+                    self.columns.insert(index, grid.width());
+                }
+                if let Some(rect) = self.layout.rect(index) {
+                    tr::paint_message(r, &mut self.board, message, worker_index, tetris_index, rect);
+                }
             }
             _ => (),
         }
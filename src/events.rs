@@ -10,7 +10,7 @@ lazy_static! {
 
 pub trait OpEvent {
     fn create(&mut self, worker_count: u8);
-    fn init(&mut self, tetris_per_worker: u32);
+    fn init(&mut self, tetris_per_worker: u32, preset: tc::Preset, goal: tc::Goal);
     fn trigger_block_event(&mut self, event: tc::BlockEvent);
     fn send_app_event(&mut self, event: tc::AppEvent);
     fn received(&mut self) -> Vec<tc::Msg>;
@@ -18,6 +18,7 @@ pub trait OpEvent {
 
 pub struct EventMgr<T> {
     worker_handles: Vec<T>,
+    codec: tc::Codec,
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -29,22 +30,19 @@ mod wasm32 {
 
     use tc;
     use asm;
-    use serde_json;
-    use libc;
 
     use std::os::raw::{c_char, c_int, c_void};
     use std::ptr;
     use std::ffi::CString;
     use std::mem;
+    use std::slice;
 
     extern "C" fn em_worker_callback_func(data: *mut c_char, size: c_int, _user_args: *mut c_void) {
-        let raw_msg: &[u8] =
-            unsafe { mem::transmute(::std::slice::from_raw_parts(data, size as usize - 1)) };
-
-        let msg = String::from_utf8(raw_msg.to_vec()).unwrap();
-        let msg = serde_json::from_str::<tc::Msg>(msg.as_str()).unwrap();
-
-        MESSAGE.lock().unwrap().push(msg);
+        let raw_msg = unsafe { slice::from_raw_parts(data as *const u8, size as usize) };
+        match tc::decode_msgs(raw_msg) {
+            Ok(mut msgs) => MESSAGE.lock().unwrap().append(&mut msgs),
+            Err(e) => println!("[main] Deserialize error: {}", e),
+        }
     }
 
     extern "C" fn main_loop_callback(arg: *mut c_void) {
@@ -55,8 +53,11 @@ mod wasm32 {
     }
 
     impl EventMgr<c_int> {
-        pub fn new() -> EventMgr<c_int> {
-            EventMgr { worker_handles: Vec::new() }
+        pub fn new(codec: tc::Codec) -> EventMgr<c_int> {
+            EventMgr {
+                worker_handles: Vec::new(),
+                codec: codec,
+            }
         }
     }
 
@@ -72,7 +73,7 @@ mod wasm32 {
             self.worker_handles.append(&mut worker_handles);
         }
 
-        fn init(&mut self, tetris_per_worker: u32) {
+        fn init(&mut self, tetris_per_worker: u32, preset: tc::Preset, goal: tc::Goal) {
             for worker_index in 0..self.worker_handles.len() {
                 self.send_app_event(tc::AppEvent::InitWorker(
                     worker_index as u8,
@@ -82,7 +83,12 @@ mod wasm32 {
 
             for worker_index in 0..self.worker_handles.len() as u8 {
                 for tetris_index in 0..tetris_per_worker {
-                    self.send_app_event(tc::AppEvent::InitTetris(worker_index, tetris_index));
+                    self.send_app_event(tc::AppEvent::InitTetris(
+                        worker_index,
+                        tetris_index,
+                        preset,
+                        goal,
+                    ));
                 }
             }
         }
@@ -91,19 +97,19 @@ mod wasm32 {
             EVENT_Q.lock().unwrap().push(event);
         }
 
+        //
+        // The worker call copies the data, so the buffer is freed on return.
+        //
         fn send_app_event(&mut self, event: tc::AppEvent) {
-            let json = serde_json::to_string(&event).expect("[main] Serialize error");
-            let send = CString::new(json).unwrap();
-            let send = send.into_raw();
-            let len = unsafe { libc::strlen(send) as i32 };
+            let mut send = self.codec.encode_event(&event);
             let method = CString::new("on").unwrap();
 
             unsafe {
                 asm::emscripten_call_worker(
                     self.worker_handles[event.worker_id() as usize],
                     method.as_ptr(),
-                    send,
-                    len,
+                    send.as_mut_ptr() as *mut c_char,
+                    send.len() as c_int,
                     Some(em_worker_callback_func),
                     ptr::null_mut(),
                 );
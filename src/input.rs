@@ -0,0 +1,320 @@
+use tc;
+
+use sdl2::GameControllerSubsystem;
+use sdl2::controller::{Button, GameController};
+use sdl2::keyboard::Keycode;
+use serde_json;
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::io::{self, Read};
+
+pub use tc::MAX_PLAYERS;
+
+//
+// One binding table per player, so several players can share a keyboard.
+//
+#[derive(Clone)]
+pub struct KeyMap {
+    bindings: Vec<(Keycode, tc::BlockEvent)>,
+}
+
+impl KeyMap {
+    pub fn new(bindings: Vec<(Keycode, tc::BlockEvent)>) -> KeyMap {
+        KeyMap { bindings: bindings }
+    }
+
+    pub fn from_names(bindings: &HashMap<String, tc::BlockEvent>) -> Result<KeyMap, ConfigError> {
+        let mut keys = Vec::with_capacity(bindings.len());
+        for (name, event) in bindings {
+            match Keycode::from_name(name) {
+                Some(keycode) => keys.push((keycode, event.clone())),
+                None => return Err(ConfigError::Key(name.clone())),
+            }
+        }
+        Ok(KeyMap::new(keys))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bindings.is_empty()
+    }
+
+    pub fn event(&self, keycode: Keycode) -> Option<tc::BlockEvent> {
+        self.bindings
+            .iter()
+            .find(|&&(key, _)| key == keycode)
+            .map(|&(_, ref event)| event.clone())
+    }
+
+    // The shared layouts, see tc::preset_keys().
+    pub fn preset(player: usize) -> KeyMap {
+        let bindings = tc::preset_keys(player)
+            .into_iter()
+            .map(|(name, event)| (Keycode::from_name(name).expect("Unknown preset key!"), event))
+            .collect();
+        KeyMap::new(bindings)
+    }
+}
+
+//
+// Game controller buttons, shared by every controller.
+//
+#[derive(Clone)]
+pub struct ButtonMap {
+    bindings: Vec<(Button, tc::BlockEvent)>,
+}
+
+impl ButtonMap {
+    pub fn new(bindings: Vec<(Button, tc::BlockEvent)>) -> ButtonMap {
+        ButtonMap { bindings: bindings }
+    }
+
+    // Names as in SDL controller mappings: "a", "dpleft", "leftshoulder"...
+    pub fn from_names(bindings: &HashMap<String, tc::BlockEvent>) -> Result<ButtonMap, ConfigError> {
+        let mut buttons = Vec::with_capacity(bindings.len());
+        for (name, event) in bindings {
+            match Button::from_string(name) {
+                Some(button) => buttons.push((button, event.clone())),
+                None => return Err(ConfigError::Button(name.clone())),
+            }
+        }
+        Ok(ButtonMap::new(buttons))
+    }
+
+    pub fn event(&self, button: Button) -> Option<tc::BlockEvent> {
+        self.bindings
+            .iter()
+            .find(|&&(b, _)| b == button)
+            .map(|&(_, ref event)| event.clone())
+    }
+
+    pub fn preset() -> ButtonMap {
+        ButtonMap::new(vec![
+            (Button::DPadLeft, tc::BlockEvent::Left),
+            (Button::DPadRight, tc::BlockEvent::Right),
+            (Button::DPadDown, tc::BlockEvent::Down),
+            (Button::DPadUp, tc::BlockEvent::Drop),
+            (Button::A, tc::BlockEvent::Rotate),
+            (Button::B, tc::BlockEvent::Rotate),
+            (Button::X, tc::BlockEvent::Hold),
+            (Button::LeftShoulder, tc::BlockEvent::Undo),
+            (Button::RightShoulder, tc::BlockEvent::Redo),
+        ])
+    }
+}
+
+pub struct Player {
+    pub keys: KeyMap,
+    pub buttons: ButtonMap,
+    // Instance id of the game controller this player uses, if any.
+    pub controller: Option<i32>,
+    pub repeat: tc::AutoRepeat,
+    // Index of the board this player controls.
+    pub board: u32,
+}
+
+impl Player {
+    pub fn new(keys: KeyMap, handling: tc::Handling, board: u32) -> Player {
+        Player {
+            keys: keys,
+            buttons: ButtonMap::preset(),
+            controller: None,
+            repeat: tc::AutoRepeat::new(handling),
+            board: board,
+        }
+    }
+
+    pub fn button_down(&mut self, button: Button) {
+        if let Some(event) = self.buttons.event(button) {
+            self.repeat.press(event);
+        }
+    }
+
+    pub fn button_up(&mut self, button: Button) {
+        if let Some(event) = self.buttons.event(button) {
+            self.repeat.release(event);
+        }
+    }
+
+    pub fn key_down(&mut self, keycode: Keycode) {
+        if let Some(event) = self.keys.event(keycode) {
+            self.repeat.press(event);
+        }
+    }
+
+    pub fn key_up(&mut self, keycode: Keycode) {
+        if let Some(event) = self.keys.event(keycode) {
+            self.repeat.release(event);
+        }
+    }
+}
+
+//
+// Settings file, in JSON:
+//
+//     {
+//         "handling": { "das": 10, "arr": 2, "soft_drop": 20 },
+//         "players": [{ "Left": "Left", "Right": "Right", "X": "Rotate" }],
+//         "buttons": { "dpleft": "Left", "dpright": "Right", "a": "Rotate" }
+//     }
+//
+// Keys use the SDL key names and buttons the SDL controller mapping names.
+// Players without an entry keep their preset, and so do the buttons when
+// none are given.
+//
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub handling: tc::Handling,
+    pub players: Vec<HashMap<String, tc::BlockEvent>>,
+    pub buttons: HashMap<String, tc::BlockEvent>,
+}
+
+impl Config {
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Config, ConfigError> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    fn buttons(&self) -> Result<ButtonMap, ConfigError> {
+        if self.buttons.is_empty() {
+            Ok(ButtonMap::preset())
+        } else {
+            ButtonMap::from_names(&self.buttons)
+        }
+    }
+
+    // `count` players on the first boards.
+    pub fn players(&self, count: usize) -> Result<Vec<Player>, ConfigError> {
+        let buttons = self.buttons()?;
+        (0..count.min(MAX_PLAYERS))
+            .map(|player| {
+                let keys = match self.players.get(player) {
+                    Some(bindings) => KeyMap::from_names(bindings)?,
+                    None => KeyMap::preset(player),
+                };
+                let mut player = Player::new(keys, self.handling.clone(), player as u32);
+                player.buttons = buttons.clone();
+                Ok(player)
+            })
+            .collect()
+    }
+
+    pub fn controllers(&self, subsystem: Option<GameControllerSubsystem>) -> Result<Controllers, ConfigError> {
+        Ok(Controllers {
+            subsystem: subsystem,
+            opened: Vec::new(),
+            buttons: self.buttons()?,
+            handling: self.handling.clone(),
+        })
+    }
+}
+
+//
+// Opens game controllers as they are plugged in. SDL also reports the ones
+// connected at start-up as added, so there is no initial scan.
+//
+// A new controller goes to the first player without one. When every player
+// has one it gets a new player on a board nobody plays.
+//
+pub struct Controllers {
+    subsystem: Option<GameControllerSubsystem>,
+    // Kept open for as long as they are plugged in.
+    opened: Vec<GameController>,
+    buttons: ButtonMap,
+    handling: tc::Handling,
+}
+
+impl Controllers {
+    pub fn none() -> Controllers {
+        Controllers {
+            subsystem: None,
+            opened: Vec::new(),
+            buttons: ButtonMap::preset(),
+            handling: tc::Handling::default(),
+        }
+    }
+
+    // `index` is the joystick device index, returns the instance id.
+    fn open(&mut self, index: u32) -> Option<i32> {
+        let controller = match self.subsystem {
+            Some(ref subsystem) => subsystem.open(index).ok()?,
+            None => return None,
+        };
+        let id = controller.instance_id();
+        println!("[main] controller {} added: {}", id, controller.name());
+        self.opened.push(controller);
+        Some(id)
+    }
+
+    pub fn added(&mut self, index: u32, players: &mut Vec<Player>, board_count: u32) {
+        let id = match self.open(index) {
+            Some(id) => id,
+            None => return,
+        };
+
+        if let Some(player) = players.iter_mut().find(|player| player.controller.is_none()) {
+            player.controller = Some(id);
+            return;
+        }
+
+        if players.len() >= MAX_PLAYERS {
+            return;
+        }
+        let free = (0..board_count).find(|board| players.iter().all(|player| player.board != *board));
+        if let Some(board) = free {
+            let mut player = Player::new(KeyMap::new(vec![]), self.handling.clone(), board);
+            player.buttons = self.buttons.clone();
+            player.controller = Some(id);
+            players.push(player);
+        }
+    }
+
+    //
+    // Players who only had the controller leave, the others keep their
+    // keys.
+    //
+    pub fn removed(&mut self, id: i32, players: &mut Vec<Player>) {
+        println!("[main] controller {} removed", id);
+        self.opened.retain(|controller| controller.instance_id() != id);
+
+        for player in players.iter_mut().filter(|player| player.controller == Some(id)) {
+            player.controller = None;
+            player.repeat.release_all();
+        }
+        players.retain(|player| player.controller.is_some() || !player.keys.is_empty());
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Key(String),
+    Button(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Io(ref e) => write!(f, "config io error: {}", e),
+            ConfigError::Json(ref e) => write!(f, "config error: {}", e),
+            ConfigError::Key(ref name) => write!(f, "unknown key: {}", name),
+            ConfigError::Button(ref name) => write!(f, "unknown button: {}", name),
+        }
+    }
+}
+
+impl error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> ConfigError {
+        ConfigError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(e: serde_json::Error) -> ConfigError {
+        ConfigError::Json(e)
+    }
+}
@@ -0,0 +1,65 @@
+use tr::Rect;
+
+//
+// Places `count` boards of the same size in a rows x cols grid that fills
+// as much of the window as it can without stretching them. The column count
+// is the one giving the largest scale, and the grid is centred.
+//
+pub struct Layout {
+    rects: Vec<Rect>,
+}
+
+impl Layout {
+    pub fn new(count: u32, board_width: u32, board_height: u32, width: u32, height: u32) -> Layout {
+        if count == 0 || board_width == 0 || board_height == 0 {
+            return Layout { rects: Vec::new() };
+        }
+
+        let fit = |cols: u32| {
+            let rows = (count + cols - 1) / cols;
+            let x = width as f32 / (cols * board_width) as f32;
+            let y = height as f32 / (rows * board_height) as f32;
+            x.min(y)
+        };
+
+        let mut cols = 1;
+        for c in 2..count + 1 {
+            if fit(c) > fit(cols) {
+                cols = c;
+            }
+        }
+        let rows = (count + cols - 1) / cols;
+        let scale = fit(cols);
+
+        let cell_width = board_width as f32 * scale;
+        let cell_height = board_height as f32 * scale;
+        let left = (width as f32 - cell_width * cols as f32) / 2.0;
+        let top = (height as f32 - cell_height * rows as f32) / 2.0;
+
+        let rects = (0..count)
+            .map(|index| {
+                let x = left + (index % cols) as f32 * cell_width;
+                let y = top + (index / cols) as f32 * cell_height;
+                Rect::new(
+                    x as i32,
+                    y as i32,
+                    (cell_width as u32).max(1),
+                    (cell_height as u32).max(1),
+                )
+            })
+            .collect();
+
+        Layout { rects: rects }
+    }
+
+    pub fn rect(&self, index: usize) -> Option<Rect> {
+        self.rects.get(index).cloned()
+    }
+
+    // Index of the board at window position (x, y).
+    pub fn index_at(&self, x: i32, y: i32) -> Option<usize> {
+        self.rects
+            .iter()
+            .position(|rect| x >= rect.x && x < rect.right() && y >= rect.y && y < rect.bottom())
+    }
+}
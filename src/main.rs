@@ -1,18 +1,129 @@
 extern crate emscripten_sys as asm;
 extern crate tetris_core as tc;
+extern crate tetris_render as tr;
 
 #[macro_use]
 extern crate lazy_static;
 extern crate libc;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 extern crate serde_json;
 extern crate sdl2;
 
 mod app;
 mod events;
+mod input;
+mod layout;
+mod render;
 
 use sdl2::render::{TextureCreator, WindowCanvas};
 use sdl2::video::WindowContext;
 
+use std::env;
+use std::fs::File;
+use std::io::BufWriter;
+
+//
+// `--json` talks to the workers in JSON instead of the binary wire format,
+// which is easier to read in the browser tools.
+//
+fn codec_arg() -> tc::Codec {
+    if env::args().any(|arg| arg == "--json") {
+        tc::Codec::Json
+    } else {
+        tc::Codec::Binary
+    }
+}
+
+//
+// `--replay <file>` plays a recorded game instead of a live one.
+//
+fn replay_arg() -> Option<tc::Replay> {
+    let path = env::args().skip_while(|arg| arg != "--replay").nth(1)?;
+    let mut file = File::open(&path).expect("Can not open the replay file!");
+    Some(tc::Replay::read_from(&mut file).expect("Can not read the replay file!"))
+}
+
+//
+// `--rules <classic|guideline|nes|tgm>` picks the rules every board is
+// played by.
+//
+fn rules_arg() -> tc::Preset {
+    match env::args().skip_while(|arg| arg != "--rules").nth(1) {
+        Some(name) => tc::Preset::from_name(&name).expect("Unknown rules!"),
+        None => tc::Preset::Classic,
+    }
+}
+
+//
+// `--sprint <lines>` plays every board until that many lines are cleared,
+// against the clock. `--bests <file>` is where personal bests are kept.
+//
+fn sprint_arg() -> tc::Goal {
+    match env::args().skip_while(|arg| arg != "--sprint").nth(1) {
+        Some(lines) => tc::Goal::from_lines(lines.parse().expect("Can not read the sprint lines!")),
+        None => tc::Goal::Endless,
+    }
+}
+
+fn bests_arg() -> String {
+    env::args()
+        .skip_while(|arg| arg != "--bests")
+        .nth(1)
+        .unwrap_or_else(|| "bests.json".to_string())
+}
+
+//
+// `--players <n>` gives 1 to 4 local players their own board and keys.
+//
+fn players_arg(config: &input::Config) -> Vec<input::Player> {
+    let count = env::args()
+        .skip_while(|arg| arg != "--players")
+        .nth(1)
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(1);
+    config.players(count).expect("Can not read the key bindings!")
+}
+
+//
+// `--config <file>` loads key bindings and handling settings, see
+// input::Config.
+//
+fn config_arg() -> input::Config {
+    match env::args().skip_while(|arg| arg != "--config").nth(1) {
+        Some(path) => {
+            let mut file = File::open(&path).expect("Can not open the config file!");
+            input::Config::read_from(&mut file).expect("Can not read the config file!")
+        }
+        None => input::Config::default(),
+    }
+}
+
+//
+// `--gif <file>` records the board player 1 is on into an animated GIF,
+// written when G is pressed. `--gif-scale <n>` sets the pixels per cell and
+// `--gif-skip <n>` keeps one image every n frames.
+//
+fn gif_arg() -> Option<tr::GifRecorder<BufWriter<File>>> {
+    let path = env::args().skip_while(|arg| arg != "--gif").nth(1)?;
+    let number = |name: &str, default: u32| {
+        env::args()
+            .skip_while(|arg| arg != name)
+            .nth(1)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    };
+
+    let file = File::create(&path).expect("Can not create the gif file!");
+    let gif = tr::GifRecorder::new(
+        BufWriter::new(file),
+        number("--gif-scale", 8),
+        number("--gif-skip", 2),
+    );
+    Some(gif.expect("Can not start the gif!"))
+}
+
 fn main() {
     let sdl_context = sdl2::init().unwrap();
     let events = sdl_context.event_pump().unwrap();
@@ -23,6 +134,7 @@ fn main() {
             app::TARGET_RENDER_WIDTH,
             app::TARGET_RENDER_HEIGHT,
         )
+        .resizable()
         .build()
         .unwrap();
     let canvas: WindowCanvas = window
@@ -33,11 +145,30 @@ fn main() {
         .unwrap();
     let texture_creator: TextureCreator<WindowContext> = canvas.texture_creator();
 
-    events::event_loop(Box::new(app::App::new(
-        canvas,
-        events,
-        &texture_creator,
-        app::WORKER_COUNT,
-        app::TETRIS_COUNT,
-    )));
+    let config = config_arg();
+    let mut app = match replay_arg() {
+        Some(replay) => app::App::playback(canvas, events, &texture_creator, replay),
+        None => {
+            app::App::new(
+                canvas,
+                events,
+                &texture_creator,
+                app::WORKER_COUNT,
+                app::TETRIS_COUNT,
+                codec_arg(),
+                rules_arg(),
+                sprint_arg(),
+                players_arg(&config),
+                config
+                    .controllers(sdl_context.game_controller().ok())
+                    .expect("Can not read the button bindings!"),
+            )
+        }
+    };
+    if let Some(gif) = gif_arg() {
+        app.record(gif);
+    }
+    app.keep_bests(bests_arg());
+
+    events::event_loop(Box::new(app));
 }
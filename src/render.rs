@@ -0,0 +1,69 @@
+use tr;
+
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
+
+//
+// tr::Renderer on the window canvas. Boards come as framebuffers and are
+// uploaded to `texture`, which has to be a streaming ABGR8888 texture of
+// the board size: that is RGBA in memory on little endian targets.
+//
+pub struct SdlRenderer<'c, 't: 'c> {
+    canvas: &'c mut Canvas<Window>,
+    texture: &'c mut Texture<'t>,
+}
+
+impl<'c, 't> SdlRenderer<'c, 't> {
+    pub fn new(canvas: &'c mut Canvas<Window>, texture: &'c mut Texture<'t>) -> SdlRenderer<'c, 't> {
+        SdlRenderer {
+            canvas: canvas,
+            texture: texture,
+        }
+    }
+}
+
+fn sdl_rect(rect: tr::Rect) -> Rect {
+    Rect::new(rect.x, rect.y, rect.width, rect.height)
+}
+
+fn sdl_color((r, g, b): tr::Rgb) -> Color {
+    Color::RGB(r, g, b)
+}
+
+impl<'c, 't> tr::Renderer for SdlRenderer<'c, 't> {
+    fn fill_rect(&mut self, rect: tr::Rect, color: tr::Rgb) {
+        if rect.width == 0 || rect.height == 0 {
+            return;
+        }
+        self.canvas.set_draw_color(sdl_color(color));
+        self.canvas.fill_rect(sdl_rect(rect)).unwrap();
+    }
+
+    fn draw_points(&mut self, points: &[(i32, i32)], color: tr::Rgb) {
+        let points: Vec<Point> = points.iter().map(|&(x, y)| Point::new(x, y)).collect();
+        self.canvas.set_draw_color(sdl_color(color));
+        self.canvas.draw_points(points.as_slice()).unwrap();
+    }
+
+    fn blit(&mut self, source: &tr::Framebuffer, dest: tr::Rect) {
+        let source_rect = Rect::new(0, 0, source.width(), source.height());
+        self.texture
+            .update(source_rect, source.as_bytes(), source.width() as usize * 4)
+            .unwrap();
+        self.canvas
+            .copy(self.texture, Some(source_rect), Some(sdl_rect(dest)))
+            .unwrap();
+    }
+
+    fn clear(&mut self, color: tr::Rgb) {
+        self.canvas.set_draw_color(sdl_color(color));
+        self.canvas.clear();
+    }
+
+    fn draw_rect(&mut self, rect: tr::Rect, color: tr::Rgb) {
+        self.canvas.set_draw_color(sdl_color(color));
+        self.canvas.draw_rect(sdl_rect(rect)).unwrap();
+    }
+}
@@ -0,0 +1,278 @@
+use {AppEvent, Block, Grid, Msg, Point, Tetris};
+
+//
+// Changes since the previous message of the same board.
+//
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Delta {
+    // (x, y, value)
+    pub cells: Vec<(u8, u8, u8)>,
+    pub block: Option<BlockUpdate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum BlockUpdate {
+    // The same block moved or rotated.
+    Move(Vec<Point>),
+    // A block locked and the next one spawned, or the state jumped.
+    Spawn(Block),
+}
+
+fn same_piece(a: &Block, b: &Block) -> bool {
+    a.type_ref() == b.type_ref() && a.color_ref() == b.color_ref() && a.next_type() == b.next_type()
+}
+
+//
+// Worker side. The first message after new() or reset() carries the full
+// state, every following one only a Delta. Each message gets the next
+// sequence number.
+//
+#[derive(Default)]
+pub struct DeltaEncoder {
+    seq: u32,
+    last: Option<(Block, Grid)>,
+}
+
+impl DeltaEncoder {
+    pub fn new() -> DeltaEncoder {
+        DeltaEncoder {
+            seq: 0,
+            last: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+
+    pub fn message(&mut self, event: AppEvent, tetris: &Tetris) -> Msg {
+        self.seq = self.seq.wrapping_add(1);
+
+        let block = tetris.get_block();
+        let grid = tetris.get_grid();
+
+        let mut msg = match self.last {
+            Some((ref last_block, ref last_grid)) => {
+                let width = grid.width();
+                let cells = grid.as_slice()
+                    .iter()
+                    .zip(last_grid.as_slice().iter())
+                    .enumerate()
+                    .filter(|&(_, (now, before))| now != before)
+                    .map(|(i, (now, _))| ((i % width) as u8, (i / width) as u8, *now))
+                    .collect();
+
+                let update = if !same_piece(&block, last_block) {
+                    Some(BlockUpdate::Spawn(block.clone()))
+                } else if block.points_ref() != last_block.points_ref() {
+                    Some(BlockUpdate::Move(block.points()))
+                } else {
+                    None
+                };
+
+                let mut msg = Msg::new(event, None, None, None);
+                msg.delta = Some(Delta {
+                    cells: cells,
+                    block: update,
+                });
+                msg
+            }
+            None => {
+                Msg::new(
+                    event,
+                    Some(block.clone()),
+                    Some(grid.visible()),
+                    Some(tetris.scheme.clone()),
+                )
+            }
+        };
+
+        msg.seq = self.seq;
+        msg.stats = Some(tetris.stats.clone());
+        msg.game_over = tetris.is_game_over();
+        msg.clearing = tetris.clearing().cloned();
+        msg.hold = tetris.hold().cloned();
+        self.last = Some((block, grid));
+        msg
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DeltaError {
+    // A message went missing: ask the worker for the full state.
+    Resync,
+    // Still waiting for the full state asked for earlier.
+    Waiting,
+}
+
+//
+// Main thread side. Turns the stream of one board back into full messages.
+//
+#[derive(Default)]
+pub struct DeltaDecoder {
+    seq: u32,
+    base: Option<Msg>,
+    resyncing: bool,
+}
+
+impl DeltaDecoder {
+    pub fn new() -> DeltaDecoder {
+        DeltaDecoder {
+            seq: 0,
+            base: None,
+            resyncing: false,
+        }
+    }
+
+    fn fail(&mut self) -> DeltaError {
+        self.base = None;
+        if self.resyncing {
+            DeltaError::Waiting
+        } else {
+            self.resyncing = true;
+            DeltaError::Resync
+        }
+    }
+
+    pub fn apply(&mut self, msg: Msg) -> Result<Msg, DeltaError> {
+        let delta = match msg.delta {
+            None => {
+                self.seq = msg.seq;
+                self.resyncing = false;
+                self.base = Some(Msg::new(
+                    msg.event.clone(),
+                    msg.block.clone(),
+                    msg.grid.clone(),
+                    msg.scheme.clone(),
+                ));
+                return Ok(msg);
+            }
+            Some(delta) => delta,
+        };
+
+        if self.base.is_none() || msg.seq != self.seq.wrapping_add(1) {
+            return Err(self.fail());
+        }
+        self.seq = msg.seq;
+
+        let base = self.base.as_mut().unwrap();
+        if let Some(ref mut grid) = base.grid {
+            for &(x, y, value) in &delta.cells {
+                grid.set(x as usize, y as usize, value);
+            }
+        }
+
+        match delta.block {
+            Some(BlockUpdate::Move(mut points)) => {
+                if let Some(ref mut block) = base.block {
+                    block.update(&mut points);
+                }
+            }
+            Some(BlockUpdate::Spawn(block)) => base.block = Some(block),
+            None => (),
+        }
+
+        let mut full = Msg::new(
+            msg.event,
+            base.block.clone(),
+            base.grid.clone(),
+            base.scheme.clone(),
+        );
+        full.seq = msg.seq;
+        full.stats = msg.stats;
+        full.game_over = msg.game_over;
+        full.clearing = msg.clearing;
+        full.hold = msg.hold;
+        full.mode = msg.mode;
+        Ok(full)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {decode_msg, BlockEvent, Codec, Goal, Mode, Preset};
+
+    #[test]
+    fn deltas_rebuild_state() {
+        let mut tetris = Tetris::with_seed(9);
+        tetris.init();
+
+        let mut encoder = DeltaEncoder::new();
+        let mut decoder = DeltaDecoder::new();
+
+        let first = encoder.message(AppEvent::InitTetris(0, 0, Preset::Classic, Goal::Endless), &tetris);
+        assert!(first.delta.is_none());
+        decoder.apply(first).unwrap();
+
+        for frame in 0..500 {
+            tetris.tick();
+            if frame % 13 == 0 {
+                tetris.event(Some(vec![BlockEvent::Rotate, BlockEvent::Drop]));
+            }
+
+            let msg = encoder.message(AppEvent::Tick(0, 0), &tetris);
+            assert!(msg.grid.is_none());
+
+            let full = decoder.apply(msg).unwrap();
+            assert_eq!(full.grid.unwrap().as_slice(), tetris.grid.as_slice());
+            let block = full.block.unwrap();
+            assert_eq!(block.points(), tetris.block.points());
+            assert_eq!(block.next_type(), tetris.block.next_type());
+        }
+    }
+
+    #[test]
+    fn gap_asks_for_resync() {
+        let mut tetris = Tetris::with_seed(9);
+        tetris.init();
+
+        let mut encoder = DeltaEncoder::new();
+        let mut decoder = DeltaDecoder::new();
+        decoder.apply(encoder.message(AppEvent::InitTetris(0, 0, Preset::Classic, Goal::Endless), &tetris)).unwrap();
+
+        encoder.message(AppEvent::Tick(0, 0), &tetris);
+        let msg = encoder.message(AppEvent::Tick(0, 0), &tetris);
+        assert_eq!(decoder.apply(msg).err(), Some(DeltaError::Resync));
+
+        let msg = encoder.message(AppEvent::Tick(0, 0), &tetris);
+        assert_eq!(decoder.apply(msg).err(), Some(DeltaError::Waiting));
+
+        encoder.reset();
+        let full = encoder.message(AppEvent::Resync(0, 0), &tetris);
+        decoder.apply(full).unwrap();
+        decoder.apply(encoder.message(AppEvent::Tick(0, 0), &tetris)).unwrap();
+    }
+
+    #[test]
+    fn mode_survives_the_wire() {
+        let mut tetris = Tetris::with_seed(9);
+        tetris.init();
+        let mut mode = Mode::new(tetris, Goal::Lines(40));
+
+        let mut encoder = DeltaEncoder::new();
+        let mut decoder = DeltaDecoder::new();
+        let mut send = |event: AppEvent, mode: &Mode| {
+            let mut msg = encoder.message(event, &mode.tetris);
+            msg.mode = mode.state();
+            let msg = decode_msg(&Codec::Binary.encode_msg(&msg)).unwrap();
+            decoder.apply(msg).unwrap()
+        };
+
+        let first = send(AppEvent::InitTetris(0, 0, Preset::Classic, Goal::Lines(40)), &mode);
+        assert_eq!(first.mode, mode.state());
+
+        for frame in 0..300 {
+            mode.tick();
+            if frame % 13 == 0 {
+                // A wasted move there and back, for the faults to change.
+                mode.event(Some(vec![BlockEvent::Left]));
+                mode.event(Some(vec![BlockEvent::Right, BlockEvent::Drop]));
+            }
+            let full = send(AppEvent::Tick(0, 0), &mode);
+            assert!(full.mode.is_some());
+            assert_eq!(full.mode, mode.state());
+        }
+        assert!(mode.faults() > 0);
+    }
+}
@@ -0,0 +1,174 @@
+use BlockEvent;
+
+//
+// How held keys repeat. All values are in frames.
+//
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Handling {
+    // Delayed Auto Shift: how long Left or Right is held before repeating.
+    pub das: u32,
+    // Auto Repeat Rate: frames between repeats, 0 goes to the wall at once.
+    pub arr: u32,
+    // A held Down falls this many times faster than gravity.
+    pub soft_drop: u32,
+}
+
+impl Default for Handling {
+    fn default() -> Handling {
+        Handling {
+            das: 10,
+            arr: 2,
+            soft_drop: 20,
+        }
+    }
+}
+
+//
+// Tracks which keys are down and turns them into block events once per
+// frame, so holding a key does not depend on the OS key repeat. Presses
+// still move once right away.
+//
+#[derive(Debug, Clone)]
+pub struct AutoRepeat {
+    handling: Handling,
+    // Held keys and the frames they have been held for.
+    held: Vec<(BlockEvent, u32)>,
+    pending: Vec<BlockEvent>,
+}
+
+impl AutoRepeat {
+    pub fn new(handling: Handling) -> AutoRepeat {
+        AutoRepeat {
+            handling: handling,
+            held: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn press(&mut self, event: BlockEvent) {
+        if self.held.iter().any(|(held, _)| *held == event) {
+            return;
+        }
+
+        self.pending.push(event.clone());
+
+        match event {
+            // The last sideways key pressed wins.
+            BlockEvent::Left | BlockEvent::Right => {
+                self.held.retain(|(held, _)| *held != BlockEvent::Left && *held != BlockEvent::Right);
+                self.held.push((event, 0));
+            }
+            BlockEvent::Down => self.held.push((event, 0)),
+            _ => (),
+        }
+    }
+
+    pub fn release(&mut self, event: BlockEvent) {
+        self.held.retain(|(held, _)| *held != event);
+    }
+
+    pub fn release_all(&mut self) {
+        self.held.clear();
+    }
+
+    //
+    // `gravity` is the frames per row the board falls at right now, and
+    // `columns` its width: an instant shift moves at most that far.
+    //
+    pub fn tick(&mut self, gravity: u32, columns: usize) -> Vec<BlockEvent> {
+        let mut events: Vec<BlockEvent> = self.pending.drain(..).collect();
+        let handling = &self.handling;
+
+        for (event, frames) in self.held.iter_mut() {
+            *frames += 1;
+
+            match *event {
+                BlockEvent::Down => {
+                    let interval = (gravity / handling.soft_drop.max(1)).max(1);
+                    if frames.is_multiple_of(interval) {
+                        events.push(BlockEvent::Down);
+                    }
+                }
+                _ if *frames < handling.das => (),
+                _ if handling.arr == 0 => {
+                    events.extend((0..columns).map(|_| event.clone()));
+                }
+                _ => {
+                    if (*frames - handling.das).is_multiple_of(handling.arr) {
+                        events.push(event.clone());
+                    }
+                }
+            }
+        }
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {COLUMNS, GRAVITY};
+
+    fn run(repeat: &mut AutoRepeat, frames: u32) -> Vec<BlockEvent> {
+        (0..frames).flat_map(|_| repeat.tick(GRAVITY, COLUMNS)).collect()
+    }
+
+    #[test]
+    fn das_then_arr() {
+        let mut repeat = AutoRepeat::new(Handling {
+            das: 5,
+            arr: 2,
+            soft_drop: 20,
+        });
+
+        repeat.press(BlockEvent::Left);
+        // Moves on press, then at frames 5, 7 and 9.
+        assert_eq!(run(&mut repeat, 9).len(), 4);
+
+        // The OS key repeat does not add moves.
+        repeat.press(BlockEvent::Left);
+        assert_eq!(run(&mut repeat, 1).len(), 0);
+
+        repeat.release(BlockEvent::Left);
+        assert_eq!(run(&mut repeat, 10).len(), 0);
+    }
+
+    #[test]
+    fn last_direction_wins() {
+        let mut repeat = AutoRepeat::new(Handling::default());
+
+        repeat.press(BlockEvent::Left);
+        repeat.press(BlockEvent::Right);
+        let events = run(&mut repeat, 30);
+        assert_eq!(events[0], BlockEvent::Left);
+        assert!(events[1..].iter().all(|e| *e == BlockEvent::Right));
+    }
+
+    #[test]
+    fn soft_drop_and_instant_arr() {
+        let mut repeat = AutoRepeat::new(Handling {
+            das: 3,
+            arr: 0,
+            soft_drop: 5,
+        });
+
+        repeat.press(BlockEvent::Down);
+        // One on press, then every GRAVITY / 5 frames.
+        let interval = GRAVITY / 5;
+        assert_eq!(run(&mut repeat, interval * 3).len(), 4);
+        repeat.release_all();
+
+        // Faster at a higher level.
+        repeat.press(BlockEvent::Down);
+        let events: Vec<BlockEvent> = (0..interval * 3).flat_map(|_| repeat.tick(GRAVITY / 2, COLUMNS)).collect();
+        assert_eq!(events.len(), 1 + 6);
+        repeat.release_all();
+
+        repeat.press(BlockEvent::Right);
+        assert_eq!(run(&mut repeat, 3).len(), 1 + COLUMNS);
+        let events: Vec<BlockEvent> = (0..3).flat_map(|_| repeat.tick(GRAVITY, 6)).collect();
+        assert_eq!(events.len(), 3 * 6);
+    }
+}
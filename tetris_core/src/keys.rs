@@ -0,0 +1,68 @@
+use BlockEvent;
+
+pub const MAX_PLAYERS: usize = 4;
+
+//
+// Default layouts for up to MAX_PLAYERS players on one keyboard: the
+// arrows, WASD, IJKL and the keypad. Keys go by their SDL names, as in
+// binding files, so every front-end starts from the same keys.
+//
+pub fn preset_keys(player: usize) -> Vec<(&'static str, BlockEvent)> {
+    match player {
+        0 => vec![
+            ("Up", BlockEvent::Rotate),
+            ("Left", BlockEvent::Left),
+            ("Right", BlockEvent::Right),
+            ("Down", BlockEvent::Down),
+            ("Space", BlockEvent::Drop),
+            ("Z", BlockEvent::Undo),
+            ("Y", BlockEvent::Redo),
+            ("C", BlockEvent::Hold),
+        ],
+        1 => vec![
+            ("W", BlockEvent::Rotate),
+            ("A", BlockEvent::Left),
+            ("D", BlockEvent::Right),
+            ("S", BlockEvent::Down),
+            ("Q", BlockEvent::Drop),
+            ("E", BlockEvent::Hold),
+        ],
+        2 => vec![
+            ("I", BlockEvent::Rotate),
+            ("J", BlockEvent::Left),
+            ("L", BlockEvent::Right),
+            ("K", BlockEvent::Down),
+            ("U", BlockEvent::Drop),
+            ("O", BlockEvent::Hold),
+        ],
+        _ => vec![
+            ("Keypad 8", BlockEvent::Rotate),
+            ("Keypad 4", BlockEvent::Left),
+            ("Keypad 6", BlockEvent::Right),
+            ("Keypad 5", BlockEvent::Down),
+            ("Keypad 0", BlockEvent::Drop),
+            ("Keypad 7", BlockEvent::Hold),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_do_not_share_keys() {
+        let mut keys: Vec<&str> = (0..MAX_PLAYERS)
+            .flat_map(|player| preset_keys(player).into_iter().map(|(key, _)| key))
+            .collect();
+        let count = keys.len();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), count);
+
+        for player in 0..MAX_PLAYERS {
+            let events: Vec<BlockEvent> = preset_keys(player).into_iter().map(|(_, event)| event).collect();
+            assert!(events.contains(&BlockEvent::Left) && events.contains(&BlockEvent::Drop));
+        }
+    }
+}
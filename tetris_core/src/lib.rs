@@ -3,7 +3,27 @@ extern crate rand;
 extern crate serde_derive;
 extern crate serde_json;
 
+mod delta;
+mod handling;
+mod keys;
+mod mode;
+mod replay;
+mod ruleset;
+mod wire;
+
+pub use delta::{BlockUpdate, Delta, DeltaDecoder, DeltaEncoder, DeltaError};
+pub use handling::{AutoRepeat, Handling};
+pub use keys::{preset_keys, MAX_PLAYERS};
+pub use mode::{clock, Bests, Goal, Mode, ModeState, Record};
+pub use replay::{Replay, ReplayError, ReplayHeader, ReplayInput, ReplayPlayer, REPLAY_MAGIC,
+                 REPLAY_VERSION, SNAPSHOT_INTERVAL};
+pub use ruleset::{Generator, Preset, Randomizer, Rotation, Ruleset};
+pub use wire::{decode_event, decode_msg, decode_msgs, Codec, WireError, WIRE_VERSION};
+
 use rand::distributions::{IndependentSample, Range};
+use std::error;
+use std::fmt;
+use replay::Recorder;
 
 type Points = Vec<Point>;
 type Color = (u8, u8, u8);
@@ -36,9 +56,18 @@ pub const BLOCK_O: &[(u8, u8)] = &[(0, 0), (1, 0), (0, 1), (1, 1)];
 // #, #, @, #
 pub const BLOCK_I: &[(u8, u8)] = &[(0, 0), (1, 0), (2, 0), (3, 0)];
 
+// The board size games start with, see Grid::with_size.
 pub const COLUMNS: usize = 10;
 pub const ROWS: usize = 20;
 
+pub const MIN_COLUMNS: usize = 4;
+pub const MAX_COLUMNS: usize = 40;
+pub const MIN_ROWS: usize = 4;
+pub const MAX_ROWS: usize = 60;
+
+// Hidden rows above the board. Blocks spawn in them and may lock in them.
+pub const BUFFER_ROWS: usize = 20;
+
 pub const DEFAULT_GRAVITY: u8 = 20;
 
 pub const SCHEME_1: [(u8, u8, u8); 7] = [
@@ -101,11 +130,8 @@ pub enum Scheme {
 }
 
 impl Scheme {
-    fn new() -> Scheme {
-        let mut rng = rand::thread_rng();
-        let between = Range::new(1, 5);
-
-        match between.ind_sample(&mut rng) {
+    fn from_rng(rng: &mut Rng) -> Scheme {
+        match rng.range(1, 6) {
             1 => Scheme::Type1,
             2 => Scheme::Type2,
             3 => Scheme::Type3,
@@ -128,6 +154,7 @@ impl Scheme {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Ticker {
     fact: u32,
     elapsed: u32,
@@ -153,85 +180,713 @@ impl Ticker {
     }
 }
 
+//
+// xorshift64*, small and fully determined by its state so a game can be
+// replayed or restored from a seed.
+//
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // splitmix64 spreads small seeds over the whole state space.
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        Rng { state: if z == 0 { 1 } else { z } }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // [low, high)
+    pub fn range(&mut self, low: u32, high: u32) -> u32 {
+        low + (self.next_u64() % (high - low) as u64) as u32
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Stats {
+    pub frames: u32,
+    pub pieces: u32,
+    pub lines: u32,
+    pub score: u32,
+}
+
+impl Stats {
+    // One level every 10 lines, starting at 1.
+    pub fn level(&self) -> u32 {
+        self.lines / 10 + 1
+    }
+
+    // Pieces per second.
+    pub fn pps(&self) -> f32 {
+        if self.frames == 0 {
+            return 0.0;
+        }
+        self.pieces as f32 * FRAMES_PER_SECOND as f32 / self.frames as f32
+    }
+
+    fn add_lines(&mut self, lines: u32, ruleset: &Ruleset) {
+        self.score += ruleset.line_score(lines) * self.level();
+        self.lines += lines;
+    }
+}
+
+pub const FRAMES_PER_SECOND: u32 = 60;
+
+pub const SNAPSHOT_VERSION: u8 = 1;
+
+// Frames a block takes to fall one row.
+pub const GRAVITY: u32 = 10;
+
+// Placements kept for BlockEvent::Undo.
+pub const UNDO_LIMIT: usize = 64;
+
+// The line clear delay the front-ends play with. Tetris itself clears at
+// once unless told otherwise, so older replays keep their timing.
+pub const LINE_CLEAR_DELAY: u32 = 20;
+
+//
+// Rows cleared by the last lock. They stay on the grid for `delay` frames
+// so front-ends can animate them, then collapse and the next block spawns.
+//
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LineClear {
+    pub rows: Vec<u8>,
+    // Frames since the lock.
+    pub frames: u32,
+    pub delay: u32,
+}
+
+impl LineClear {
+    // From 0 at the lock to 1 when the rows collapse.
+    pub fn progress(&self) -> f32 {
+        if self.delay == 0 {
+            return 1.0;
+        }
+        self.frames as f32 / self.delay as f32
+    }
+}
+
+//
+// Everything needed to rebuild a game. The next block is kept inside
+// `block`.
+//
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u8,
+    pub seed: u64,
+    pub block: Block,
+    pub grid: Grid,
+    pub ticker: Ticker,
+    pub scheme: Scheme,
+    pub rng: Rng,
+    pub stats: Stats,
+    pub started: bool,
+    #[serde(default)]
+    pub game_over: bool,
+    #[serde(default)]
+    pub clearing: Option<LineClear>,
+    #[serde(default)]
+    pub entry: u32,
+    #[serde(default)]
+    pub irs: bool,
+    #[serde(default)]
+    pub ihs: bool,
+    #[serde(default)]
+    pub hold: Option<BlockType>,
+    #[serde(default)]
+    pub hold_used: bool,
+    #[serde(default)]
+    pub ruleset: Ruleset,
+    #[serde(default)]
+    pub generator: Generator,
+    #[serde(default)]
+    pub previews: Vec<BlockType>,
+    #[serde(default)]
+    pub grounded: u32,
+}
+
+impl Snapshot {
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&self)
+    }
+
+    pub fn from_json(json: &str) -> Result<Snapshot, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SnapshotError {
+    Version(u8),
+    // The grid is out of the Grid::with_size() limits.
+    Size(SizeError),
+    // The grid has this many cells, not width * (buffer + height).
+    Cells(usize),
+}
+
+#[derive(Clone)]
 pub struct Tetris {
     pub block: Block,
     pub grid: Grid,
     pub ticker: Ticker,
     pub scheme: Scheme,
+    pub seed: u64,
+    pub stats: Stats,
+    pub ruleset: Ruleset,
+    rng: Rng,
+    generator: Generator,
+    // Blocks after the next one, as many as the ruleset previews.
+    previews: Vec<BlockType>,
+    // Frames the block has spent on the ground.
+    grounded: u32,
+    started: bool,
+    game_over: bool,
+    clearing: Option<LineClear>,
+    // Frames left of the entry delay.
+    entry: u32,
+    // Initial rotation and hold, asked for while waiting to spawn.
+    irs: bool,
+    ihs: bool,
+    hold: Option<BlockType>,
+    // Hold works once per block.
+    hold_used: bool,
+    // The block that locked last, where it locked.
+    locked: Option<Block>,
+    recorder: Option<Recorder>,
+    undo_limit: usize,
+    piece_start: Option<Snapshot>,
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
 }
 
 impl Tetris {
     pub fn new() -> Tetris {
-        let scheme = Scheme::new();
+        Tetris::with_seed(rand::random())
+    }
+
+    pub fn with_seed(seed: u64) -> Tetris {
+        Tetris::with_ruleset(seed, Ruleset::default())
+    }
+
+    pub fn with_ruleset(seed: u64, ruleset: Ruleset) -> Tetris {
+        let mut rng = Rng::new(seed);
+        let scheme = Scheme::from_rng(&mut rng);
+        let mut generator = Generator::default();
+        let first = generator.next(ruleset.randomizer, &mut rng);
 
         Tetris {
-            block: Block::new(BlockType::random(), &scheme),
+            block: Block::new(first, &scheme),
             grid: Grid::new(),
-            ticker: Ticker::new(10),
+            ticker: Ticker::new(ruleset.gravity(1)),
             scheme: scheme,
+            seed: seed,
+            stats: Stats::default(),
+            ruleset: ruleset,
+            rng: rng,
+            generator: generator,
+            previews: Vec::new(),
+            grounded: 0,
+            started: false,
+            game_over: false,
+            clearing: None,
+            entry: 0,
+            irs: false,
+            ihs: false,
+            hold: None,
+            hold_used: false,
+            locked: None,
+            recorder: None,
+            undo_limit: UNDO_LIMIT,
+            piece_start: None,
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    pub fn from_snapshot(snapshot: Snapshot) -> Result<Tetris, SnapshotError> {
+        let mut tetris = Tetris::with_ruleset(snapshot.seed, snapshot.ruleset.clone());
+        tetris.restore(snapshot)?;
+        Ok(tetris)
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            seed: self.seed,
+            block: self.block.clone(),
+            grid: self.grid.clone(),
+            ticker: self.ticker.clone(),
+            scheme: self.scheme.clone(),
+            rng: self.rng.clone(),
+            stats: self.stats.clone(),
+            started: self.started,
+            game_over: self.game_over,
+            clearing: self.clearing.clone(),
+            entry: self.entry,
+            irs: self.irs,
+            ihs: self.ihs,
+            hold: self.hold.clone(),
+            hold_used: self.hold_used,
+            ruleset: self.ruleset.clone(),
+            generator: self.generator.clone(),
+            previews: self.previews.clone(),
+            grounded: self.grounded,
+        }
+    }
+
+    //
+    // Replaces the game state in place. The grid is copied into the existing
+    // buffer so views of it stay valid. A running recording is dropped since
+    // it can no longer be replayed from the seed.
+    //
+    pub fn restore(&mut self, snapshot: Snapshot) -> Result<(), SnapshotError> {
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::Version(snapshot.version));
+        }
+        snapshot.grid.check()?;
+
+        self._load(snapshot);
+        self.recorder = None;
+        self.undo.clear();
+        self.redo.clear();
+        self.piece_start = Some(self.snapshot());
+        Ok(())
+    }
+
+    fn _load(&mut self, snapshot: Snapshot) {
+        self.block = snapshot.block;
+        self.grid.load(&snapshot.grid);
+        self.ticker = snapshot.ticker;
+        self.scheme = snapshot.scheme;
+        self.seed = snapshot.seed;
+        self.rng = snapshot.rng;
+        self.stats = snapshot.stats;
+        self.started = snapshot.started;
+        self.game_over = snapshot.game_over;
+        self.clearing = snapshot.clearing;
+        self.entry = snapshot.entry;
+        self.irs = snapshot.irs;
+        self.ihs = snapshot.ihs;
+        self.hold = snapshot.hold;
+        self.hold_used = snapshot.hold_used;
+        self.ruleset = snapshot.ruleset;
+        self.generator = snapshot.generator;
+        self.previews = snapshot.previews;
+        self.grounded = snapshot.grounded;
+        self.locked = None;
+    }
+
+    // 0 turns undo off.
+    pub fn set_undo_limit(&mut self, limit: usize) {
+        self.undo_limit = limit;
+        while self.undo.len() > limit {
+            self.undo.remove(0);
+        }
+        self.redo.truncate(limit);
+        if limit == 0 {
+            self.piece_start = None;
+        }
+    }
+
+    //
+    // Steps back to the moment the last placed block spawned. The current
+    // block's spawn state goes to the redo stack. Frames keep counting.
+    //
+    pub fn undo(&mut self) -> bool {
+        let snapshot = match self.undo.pop() {
+            Some(snapshot) => snapshot,
+            None => return false,
+        };
+
+        if let Some(current) = self.piece_start.take() {
+            self.redo.push(current);
+        }
+        self._travel(snapshot);
+        true
+    }
+
+    pub fn redo(&mut self) -> bool {
+        let snapshot = match self.redo.pop() {
+            Some(snapshot) => snapshot,
+            None => return false,
+        };
+
+        if let Some(current) = self.piece_start.take() {
+            self.undo.push(current);
+        }
+        self._travel(snapshot);
+        true
+    }
+
+    fn _travel(&mut self, snapshot: Snapshot) {
+        let frames = self.stats.frames;
+        self.piece_start = Some(snapshot.clone());
+        self._load(snapshot);
+        self.stats.frames = frames;
+    }
+
+    fn _mark_piece_start(&mut self) {
+        if self.undo_limit == 0 {
+            return;
+        }
+
+        if let Some(previous) = self.piece_start.take() {
+            self.undo.push(previous);
+            if self.undo.len() > self.undo_limit {
+                self.undo.remove(0);
+            }
         }
+        self.redo.clear();
+        self.piece_start = Some(self.snapshot());
     }
 
     pub fn init(&mut self) {
-        self.block.align_to_start();
+        self.block.align_to_start(self.grid.width());
 
         if self.block.next_ref().is_none() {
-            self.block.load_next(&self.scheme);
+            while self.previews.len() + 1 < self.ruleset.previews as usize {
+                let block_type = self.generator.next(self.ruleset.randomizer, &mut self.rng);
+                self.previews.push(block_type);
+            }
+            let next = self._draw();
+            self.block.load_next(next, &self.scheme);
+        }
+
+        if self.piece_start.is_none() {
+            self._mark_piece_start();
         }
     }
 
+    //
+    // Records every following tick() and event() call. Only a game that has
+    // not been played yet can be recorded, because the replay starts from
+    // the seed.
+    //
+    pub fn start_recording(&mut self) -> bool {
+        if self.recorder.is_some() {
+            return true;
+        }
+
+        if self.started {
+            return false;
+        }
+
+        let mut header = ReplayHeader::new(self.seed, self.scheme.clone());
+        header.ruleset = self.ruleset.clone();
+        header.columns = self.grid.width();
+        header.rows = self.grid.height();
+        header.buffer = self.grid.buffer();
+        self.recorder = Some(Recorder::new(header));
+        true
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    pub fn take_replay(&mut self) -> Option<Replay> {
+        self.recorder.take().map(|recorder| recorder.finish())
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.game_over
+    }
+
+    // The rows waiting to collapse, if any.
+    pub fn clearing(&self) -> Option<&LineClear> {
+        self.clearing.as_ref()
+    }
+
+    // Between a lock and the next spawn: nothing is falling.
+    pub fn is_waiting(&self) -> bool {
+        self.clearing.is_some() || self.entry > 0
+    }
+
+    pub fn hold(&self) -> Option<&BlockType> {
+        self.hold.as_ref()
+    }
+
+    pub fn last_locked(&self) -> Option<&Block> {
+        self.locked.as_ref()
+    }
+
+    // The next block first, then the ones after it.
+    pub fn previews(&self) -> Vec<BlockType> {
+        self.block.next_type().into_iter().chain(self.previews.iter().cloned()).collect()
+    }
+
+    // The block after the next one goes into the previews.
+    fn _draw(&mut self) -> BlockType {
+        let block_type = self.generator.next(self.ruleset.randomizer, &mut self.rng);
+        self.previews.push(block_type);
+        self.previews.remove(0)
+    }
+
     pub fn tick(&mut self) {
-        let ref mut ticker = self.ticker;
-        let ref mut block = self.block;
-        let ref mut grid = self.grid;
+        if self.game_over {
+            return;
+        }
+
+        self.started = true;
 
-        if ticker.tick() {
-            block.down(|points| !grid.is_empty(points));
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.tick();
         }
 
-        if !grid.is_empty_below(block.points_ref()) {
-            grid.fill(&block);
-            grid.erase_full_row(&block);
-            block.apply_next(&self.scheme);
+        self.stats.frames += 1;
+
+        if self.clearing.is_some() {
+            self._tick_clearing();
+            return;
+        }
+        if self.entry > 0 {
+            self.entry -= 1;
+            if self.entry == 0 {
+                self._spawn(false);
+            }
+            return;
+        }
+
+        {
+            let ref mut block = self.block;
+            let ref grid = self.grid;
+
+            if self.ticker.tick() {
+                block.down(|points| !grid.fits(points));
+            }
+        }
+
+        if self.grid.is_empty_below(self.block.points_ref()) {
+            self.grounded = 0;
+        } else {
+            self.grounded += 1;
+            if self.grounded > self.ruleset.lock_delay {
+                self.lock();
+            }
         }
     }
 
+    //
+    // The game is over on a lock out, when a block locks wholly above the
+    // visible rows or partly above the hidden ones, or on a block out, when
+    // the next one has no room to spawn. Full rows wait in `clearing` for the line clear
+    // delay, then the entry delay runs, then the next block spawns. Either
+    // delay can be 0.
+    //
+    fn lock(&mut self) {
+        let buffer = self.grid.buffer() as i32;
+        let points = self.block.points_ref();
+        let locked_out = points.iter().all(|point| point.y() < 0) ||
+            points.iter().any(|point| point.y() < -buffer);
+
+        self.grid.fill(&self.block);
+        self.locked = Some(self.block.clone());
+        let rows = self.grid.full_rows(&self.block);
+        self.stats.add_lines(rows.len() as u32, &self.ruleset);
+        self.stats.pieces += 1;
+        self.ticker.fact = self.ruleset.gravity(self.stats.level());
+        self.grounded = 0;
+
+        if !rows.is_empty() && self.ruleset.line_clear_delay > 0 && !locked_out {
+            self.clearing = Some(LineClear {
+                rows: rows.iter().filter(|&&row| row >= 0).map(|&row| row as u8).collect(),
+                frames: 0,
+                delay: self.ruleset.line_clear_delay,
+            });
+            return;
+        }
+
+        self.grid.erase_full_row(&self.block);
+        self._enter(locked_out);
+    }
+
+    fn _tick_clearing(&mut self) {
+        let done = match self.clearing {
+            Some(ref mut clearing) => {
+                clearing.frames += 1;
+                clearing.frames >= clearing.delay
+            }
+            None => return,
+        };
+
+        if done {
+            self.clearing = None;
+            self.grid.erase_full_row(&self.block);
+            self._enter(false);
+        }
+    }
+
+    // Starts the entry delay. A lock out ends the game without one.
+    fn _enter(&mut self, locked_out: bool) {
+        if self.ruleset.spawn_delay > 0 && !locked_out {
+            self.entry = self.ruleset.spawn_delay;
+        } else {
+            self._spawn(locked_out);
+        }
+    }
+
+    fn _spawn(&mut self, locked_out: bool) {
+        let next = self._draw();
+        self.block.apply_next(next, &self.scheme, self.grid.width());
+        self.hold_used = false;
+
+        if self.ihs {
+            self.ihs = false;
+            self._hold();
+        }
+        if self.irs {
+            self.irs = false;
+            self._rotate();
+        }
+
+        if locked_out || !self.grid.is_empty(self.block.points_ref()) {
+            self.game_over = true;
+        }
+        self._mark_piece_start();
+    }
+
+    fn _rotate(&mut self) {
+        self._turn();
+        self.block.adjust_bound(self.grid.width(), self.grid.height());
+    }
+
+    //
+    // Classic turns whatever is in the way, the other rotation systems
+    // take the first kick where the block fits, or don't turn.
+    //
+    fn _turn(&mut self) {
+        if self.block.type_ref() == &BlockType::O {
+            return;
+        }
+        if self.ruleset.rotation == Rotation::Classic {
+            self.block.rotate();
+            return;
+        }
+
+        let kicks = self.ruleset.kicks(self.block.type_ref(), self.block.orientation);
+        let mut turned = self.block.clone();
+        turned.rotate();
+        for (x, y) in kicks {
+            let mut kicked = turned.clone();
+            kicked.shift(|| (x, y));
+            if self.grid.fits(kicked.points_ref()) {
+                self.block = kicked;
+                return;
+            }
+        }
+    }
+
+    //
+    // Puts the block aside and takes the held one, or the next one the
+    // first time.
+    //
+    fn _hold(&mut self) {
+        if self.hold_used || !self.ruleset.hold {
+            return;
+        }
+
+        let current = self.block.type_ref().clone();
+        match self.hold.take() {
+            Some(held) => {
+                let next = self.block.next.take();
+                self.block = Block::new(held, &self.scheme);
+                self.block.next = next;
+                self.block.align_to_start(self.grid.width());
+            }
+            None => {
+                let next = self._draw();
+                self.block.apply_next(next, &self.scheme, self.grid.width());
+            }
+        }
+        self.hold = Some(current);
+        self.hold_used = true;
+    }
+
     pub fn event(&mut self, block_events: Option<Vec<BlockEvent>>) {
         if block_events.is_none() {
             return;
         }
 
         let block_events = block_events.unwrap();
+        self.started = true;
 
-        let ref mut block = self.block;
-        let ref mut grid = self.grid;
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.event(&block_events);
+        }
 
         for event in block_events {
             match event {
-                BlockEvent::Rotate => {
-                    if block.type_ref() != &BlockType::O {
-                        block.rotate();
-                    }
+                BlockEvent::Undo => {
+                    self.undo();
+                    continue;
+                }
+                BlockEvent::Redo => {
+                    self.redo();
+                    continue;
+                }
+                _ if self.game_over => continue,
+                // Only initial rotation and hold count before the spawn.
+                BlockEvent::Rotate if self.is_waiting() => {
+                    self.irs = true;
+                    continue;
+                }
+                BlockEvent::Hold if self.is_waiting() => {
+                    self.ihs = true;
+                    continue;
+                }
+                _ if self.is_waiting() => continue,
+                BlockEvent::Hold => {
+                    self._hold();
+                    continue;
                 }
-                BlockEvent::Left => block.left(|points| !grid.is_empty(points)),
-                BlockEvent::Right => block.right(|points| !grid.is_empty(points)),
-                BlockEvent::Down => block.down(|points| !grid.is_empty(points)),
-                BlockEvent::Drop => block.drop(|points| !grid.is_empty(points)),
                 _ => (),
-            };
+            }
+
+            if event == BlockEvent::Rotate {
+                self._turn();
+            } else {
+                let ref mut block = self.block;
+                let ref grid = self.grid;
+
+                match event {
+                    BlockEvent::Left => block.left(|points| !grid.is_empty(points)),
+                    BlockEvent::Right => block.right(|points| !grid.is_empty(points)),
+                    BlockEvent::Down => block.down(|points| !grid.fits(points)),
+                    BlockEvent::Drop => block.drop(grid.height(), |points| !grid.is_empty(points)),
+                    _ => (),
+                };
+            }
 
-            if !grid.is_empty_below(block.points_ref()) {
-                grid.fill(block);
-                grid.erase_full_row(block);
+            // With a lock delay, only a drop locks before the next tick.
+            let grounded = !self.grid.is_empty_below(self.block.points_ref());
+            if grounded && (self.ruleset.lock_delay == 0 || event == BlockEvent::Drop) {
+                self.lock();
                 break;
             }
 
-            block.adjust_bound();
+            self.block.adjust_bound(self.grid.width(), self.grid.height());
         }
     }
 
+    // Without points while waiting to spawn, the next block is still there.
     pub fn get_block(&self) -> Block {
-        self.block.clone()
+        let mut block = self.block.clone();
+        if self.is_waiting() {
+            block.points.clear();
+        }
+        block
     }
 
     pub fn get_grid(&self) -> Grid {
@@ -245,6 +900,9 @@ pub struct Block {
     color: Color,
     points: Points,
     next: Option<Box<Block>>,
+    // Quarter turns clockwise from the spawn.
+    #[serde(default)]
+    orientation: u8,
 }
 
 impl Block {
@@ -257,25 +915,27 @@ impl Block {
             points: points,
             color: color,
             next: None,
+            orientation: 0,
         }
     }
 
-    pub fn load_next(&mut self, scheme: &Scheme) {
-        self.next = Some(Box::new(Block::new(BlockType::random(), scheme)));
+    pub fn load_next(&mut self, block_type: BlockType, scheme: &Scheme) {
+        self.next = Some(Box::new(Block::new(block_type, scheme)));
     }
 
-    pub fn apply_next(&mut self, scheme: &Scheme) {
+    pub fn apply_next(&mut self, next_type: BlockType, scheme: &Scheme, columns: usize) {
         let mut block = self.next.take().expect("Can not apply a next block!");
         self.block_type = block.block_type.clone();
         self.color = block.color;
+        self.orientation = 0;
         self.update(block.points_ref_mut());
-        self.align_to_start();
-        self.load_next(scheme);
+        self.align_to_start(columns);
+        self.load_next(next_type, scheme);
     }
 
-    pub fn align_to_start(&mut self) {
+    pub fn align_to_start(&mut self, columns: usize) {
         let range = self.range();
-        let x = (COLUMNS / 2 - range.width() / 2) as i32;
+        let x = (columns / 2 - range.width() / 2) as i32;
         let y = range.height() as i32 * -1;
         self.shift(|| (x, y));
     }
@@ -292,6 +952,10 @@ impl Block {
         }
     }
 
+    pub fn orientation(&self) -> u8 {
+        self.orientation
+    }
+
     pub fn type_ref(&self) -> &BlockType {
         &self.block_type
     }
@@ -340,6 +1004,8 @@ impl Block {
             .collect();
 
         self.update(&mut points);
+        // A quarter turn counterclockwise.
+        self.orientation = (self.orientation + 3) % 4;
     }
 
     pub fn shift<F>(&mut self, mut f: F)
@@ -387,13 +1053,13 @@ impl Block {
         }
     }
 
-    pub fn drop<GARD>(&mut self, rollback_gard: GARD)
+    pub fn drop<GARD>(&mut self, rows: usize, rollback_gard: GARD)
     where
         GARD: Fn(&Points) -> bool,
     {
         let range = self.range();
         let start_y = range.y() + range.height() as i32;
-        for _ in start_y..ROWS as i32 {
+        for _ in start_y..rows as i32 {
             self.shift(|| (0, 1));
             if rollback_gard(self.points_ref()) {
                 self.shift(|| (0, -1));
@@ -429,11 +1095,11 @@ impl Block {
         Rect::new(min_x, min_y, width, height)
     }
 
-    pub fn adjust_bound(&mut self) {
+    pub fn adjust_bound(&mut self, columns: usize, rows: usize) {
         let range = self.range();
         self._adjust_left_bound(&range);
-        self._adjust_right_bound(&range);
-        self._adjust_bottom_bound(&range);
+        self._adjust_right_bound(&range, columns as i32);
+        self._adjust_bottom_bound(&range, rows as i32);
     }
 
     fn _adjust_left_bound(&mut self, range: &Rect) {
@@ -442,56 +1108,171 @@ impl Block {
         }
     }
 
-    fn _adjust_right_bound(&mut self, range: &Rect) {
+    fn _adjust_right_bound(&mut self, range: &Rect, columns: i32) {
         let right = range.x() + range.width() as i32;
-        if right >= COLUMNS as i32 {
-            self.shift(|| (COLUMNS as i32 - right, 0));
+        if right >= columns {
+            self.shift(|| (columns - right, 0));
         }
     }
 
-    fn _adjust_bottom_bound(&mut self, range: &Rect) {
+    fn _adjust_bottom_bound(&mut self, range: &Rect, rows: i32) {
         let bottom = range.y() + range.height() as i32;
-        if bottom >= ROWS as i32 {
-            self.shift(|| (0, ROWS as i32 - bottom));
+        if bottom >= rows {
+            self.shift(|| (0, rows - bottom));
         }
     }
 }
 
+//
+// Cells are stored row by row in one contiguous buffer that is allocated once
+// and never resized, so a pointer to it stays valid for the life of the grid.
+// The `buffer` rows above the visible ones have negative y. They hold cells
+// like any other row but are not part of `as_slice()`.
+//
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Grid {
-    data: Vec<Vec<u8>>,
+    #[serde(default = "default_columns")]
+    width: usize,
+    #[serde(default = "default_rows")]
+    height: usize,
+    #[serde(default)]
+    buffer: usize,
+    data: Vec<u8>,
+}
+
+fn default_columns() -> usize {
+    COLUMNS
+}
+
+fn default_rows() -> usize {
+    ROWS
+}
+
+fn check_size(width: usize, height: usize, buffer: usize) -> Result<(), SizeError> {
+    if !(MIN_COLUMNS..=MAX_COLUMNS).contains(&width) {
+        return Err(SizeError::Columns(width));
+    }
+    if !(MIN_ROWS..=MAX_ROWS).contains(&height) {
+        return Err(SizeError::Rows(height));
+    }
+    if buffer > MAX_ROWS {
+        return Err(SizeError::Buffer(buffer));
+    }
+    Ok(())
 }
 
 impl Grid {
     pub fn new() -> Grid {
-        Grid { data: vec![vec![0_u8; COLUMNS]; ROWS] }
+        Grid::with_size(COLUMNS, ROWS, BUFFER_ROWS).unwrap()
     }
 
-    pub fn get_data(&self) -> &Vec<Vec<u8>> {
-        &self.data
+    // MIN_COLUMNS to MAX_COLUMNS wide, MIN_ROWS to MAX_ROWS high, and up to
+    // MAX_ROWS hidden rows above.
+    pub fn with_size(width: usize, height: usize, buffer: usize) -> Result<Grid, SizeError> {
+        check_size(width, height, buffer)?;
+
+        Ok(Grid {
+            width: width,
+            height: height,
+            buffer: buffer,
+            data: vec![0_u8; width * (buffer + height)],
+        })
+    }
+
+    // A deserialized grid has to be as big as its size says.
+    fn check(&self) -> Result<(), SnapshotError> {
+        check_size(self.width, self.height, self.buffer).map_err(SnapshotError::Size)?;
+        if self.data.len() != self.width * (self.buffer + self.height) {
+            return Err(SnapshotError::Cells(self.data.len()));
+        }
+        Ok(())
+    }
+
+    //
+    // Takes the cells of `other`. A grid of the same size is copied into the
+    // existing buffer, so views of it stay valid; only a resize reallocates.
+    //
+    fn load(&mut self, other: &Grid) {
+        if self.data.len() == other.data.len() {
+            self.width = other.width;
+            self.height = other.height;
+            self.buffer = other.buffer;
+            self.data.copy_from_slice(&other.data);
+        } else {
+            self.clone_from(other);
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    // Visible rows.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // Hidden rows above the visible ones.
+    pub fn buffer(&self) -> usize {
+        self.buffer
+    }
+
+    // The visible rows.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data[self.buffer * self.width..]
+    }
+
+    // A copy without the hidden rows, which is all a front-end draws.
+    pub fn visible(&self) -> Grid {
+        Grid {
+            width: self.width,
+            height: self.height,
+            buffer: 0,
+            data: self.as_slice().to_vec(),
+        }
+    }
+
+    pub fn get_data(&self) -> Vec<&[u8]> {
+        self.as_slice().chunks(self.width).collect()
+    }
+
+    fn _index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || x >= self.width as i32 || y < -(self.buffer as i32) || y >= self.height as i32 {
+            return None;
+        }
+        Some((y + self.buffer as i32) as usize * self.width + x as usize)
     }
 
     fn _check_index_range(&self, point: &Point) -> bool {
-        point.y() >= 0 && point.y() < ROWS as i32 && point.x() >= 0 && point.x() < COLUMNS as i32
+        self._index(point.x(), point.y()).is_some()
+    }
+
+    // 0 outside the grid.
+    fn _cell(&self, x: i32, y: i32) -> u8 {
+        self._index(x, y).map_or(0, |i| self.data[i])
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, value: u8) {
+        if x < self.width && y < self.height {
+            self.data[(y + self.buffer) * self.width + x] = value;
+        }
     }
 
     pub fn fill(&mut self, block: &Block) {
         for point in block.points_ref() {
-            if self._check_index_range(point) {
-                self.data[point.y() as usize][point.x() as usize] = block.block_type.index();
+            if let Some(i) = self._index(point.x(), point.y()) {
+                self.data[i] = block.block_type.index();
             }
         }
     }
 
     pub fn is_empty_below(&self, points: &Points) -> bool {
         for point in points {
-            if point.y() + 1 == ROWS as i32 {
+            if point.y() + 1 == self.height as i32 {
                 return false;
             }
 
-            if self._check_index_range(point) && point.y() + 1 < ROWS as i32 &&
-                self.data[point.y() as usize + 1][point.x() as usize] > 0
-            {
+            if self._check_index_range(point) && self._cell(point.x(), point.y() + 1) > 0 {
                 return false;
             }
         }
@@ -499,42 +1280,75 @@ impl Grid {
     }
 
     pub fn is_empty(&self, points: &Points) -> bool {
-        points
-            .iter()
-            .filter(|point| self._check_index_range(point))
-            .filter(|point| {
-                self.data[point.y() as usize][point.x() as usize] > 0
-            })
-            .collect::<Vec<&Point>>()
-            .len() == 0
+        points.iter().all(|point| self._cell(point.x(), point.y()) == 0)
     }
 
-    fn _is_full(&self, r_index: usize) -> bool {
-        !self.data[r_index].contains(&0)
+    // Inside the walls and floor and on empty cells. Above the top is free.
+    pub fn fits(&self, points: &Points) -> bool {
+        points.iter().all(|point| {
+            point.x() >= 0 && point.x() < self.width as i32 && point.y() < self.height as i32 &&
+                self._cell(point.x(), point.y()) == 0
+        })
     }
 
+    fn _is_full(&self, y: i32) -> bool {
+        match self._index(0, y) {
+            Some(start) => !self.data[start..start + self.width].contains(&0),
+            None => false,
+        }
+    }
+
+    // Everything above the row, the hidden rows too, moves down one.
     pub fn remove_row(&mut self, r_index: usize) {
-        self.data.remove(r_index);
-        self.data.insert(0, vec![0_u8; COLUMNS]);
+        self._remove_row(r_index as i32);
+    }
+
+    fn _remove_row(&mut self, y: i32) {
+        let width = self.width;
+        self.data.copy_within(0..(y + self.buffer as i32) as usize * width, width);
+        for cell in &mut self.data[0..width] {
+            *cell = 0;
+        }
     }
 
-    pub fn erase_full_row(&mut self, block: &Block) {
+    // Full rows the block covers, top to bottom. Hidden rows have negative y.
+    pub fn full_rows(&self, block: &Block) -> Vec<i32> {
         let range = block.range();
+        (range.y()..range.y() + range.height() as i32)
+            .filter(|&y| self._is_full(y))
+            .collect()
+    }
 
-        for r in range.y()..range.y() + range.height() as i32 {
-            if r < 0 || r >= ROWS as i32 {
-                continue;
-            }
+    pub fn erase_full_row(&mut self, block: &Block) -> usize {
+        let rows = self.full_rows(block);
+        // Top to bottom, so removing a row does not move the ones left.
+        for &y in rows.iter() {
+            self._remove_row(y);
+        }
+        rows.len()
+    }
+}
 
-            let r_index = r as usize;
+#[derive(Debug, Clone, PartialEq)]
+pub enum SizeError {
+    Columns(usize),
+    Rows(usize),
+    Buffer(usize),
+}
 
-            if self._is_full(r_index) {
-                self.remove_row(r_index);
+impl fmt::Display for SizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SizeError::Columns(n) => {
+                write!(f, "{} columns, not within {} to {}", n, MIN_COLUMNS, MAX_COLUMNS)
             }
+            SizeError::Rows(n) => write!(f, "{} rows, not within {} to {}", n, MIN_ROWS, MAX_ROWS),
+            SizeError::Buffer(n) => write!(f, "{} hidden rows, more than {}", n, MAX_ROWS),
         }
     }
 }
 
+impl error::Error for SizeError {}
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum BlockType {
@@ -567,6 +1381,10 @@ impl BlockType {
         BlockType::new(between.ind_sample(&mut rng))
     }
 
+    pub fn from_rng(rng: &mut Rng) -> BlockType {
+        BlockType::new(rng.range(1, 8) as u8)
+    }
+
     pub fn index(&self) -> u8 {
         match *self {
             BlockType::T => 1,
@@ -615,15 +1433,27 @@ pub enum BlockEvent {
     Down,
     Drop,
     Rotate,
+    Undo,
+    Redo,
     None,
+    Hold,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum AppEvent {
     InitWorker(u8 /*worker index*/, u32 /*tetris count*/),
-    InitTetris(u8 /*worker index*/, u32 /*tetris id*/),
+    InitTetris(u8 /*worker index*/, u32 /*tetris id*/, Preset, Goal),
     Tick(u8 /*worker index*/, u32 /*tetris id*/),
     User(u8 /*worker index*/, u32 /*tetris id*/, Option<Vec<BlockEvent>>),
+    Resync(u8 /*worker index*/, u32 /*tetris id*/),
+    // Every board of the worker, answered with one message list.
+    TickAll(u8 /*worker index*/),
+    UserAll(u8 /*worker index*/, Option<Vec<BlockEvent>>),
+    // One frame of input for some boards of the worker, each its own.
+    UserEach(u8 /*worker index*/, Vec<(u32 /*tetris id*/, Vec<BlockEvent>)>),
+    // Worker reply to an event it could not handle, with the event if it
+    // could be decoded.
+    Error(u8 /*worker index*/, WorkerError, Option<Box<AppEvent>>),
 }
 
 impl AppEvent {
@@ -634,28 +1464,103 @@ impl AppEvent {
     pub fn worker_id(&self) -> u8 {
         match *self {
             AppEvent::InitWorker(worker_index, _) |
-            AppEvent::InitTetris(worker_index, _) |
+            AppEvent::InitTetris(worker_index, _, _, _) |
             AppEvent::Tick(worker_index, _) |
-            AppEvent::User(worker_index, _, _) => worker_index,
+            AppEvent::User(worker_index, _, _) |
+            AppEvent::Resync(worker_index, _) |
+            AppEvent::TickAll(worker_index) |
+            AppEvent::UserAll(worker_index, _) |
+            AppEvent::UserEach(worker_index, _) |
+            AppEvent::Error(worker_index, _, _) => worker_index,
         }
     }
 
+    // 0 for the events that address every board of a worker.
     pub fn tetris_id(&self) -> u32 {
         match *self {
             AppEvent::InitWorker(_, tetris_id) |
-            AppEvent::InitTetris(_, tetris_id) |
+            AppEvent::InitTetris(_, tetris_id, _, _) |
             AppEvent::Tick(_, tetris_id) |
-            AppEvent::User(_, tetris_id, _) => tetris_id,
+            AppEvent::User(_, tetris_id, _) |
+            AppEvent::Resync(_, tetris_id) => tetris_id,
+            AppEvent::TickAll(_) |
+            AppEvent::UserAll(_, _) |
+            AppEvent::UserEach(_, _) => 0,
+            AppEvent::Error(_, _, ref event) => event.as_ref().map_or(0, |e| e.tetris_id()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum WorkerError {
+    // The event could not be decoded.
+    Decode,
+    AlreadyInitialized,
+    NotInitialized,
+    // The event is addressed to another worker.
+    WrongWorker,
+    NoSuchBoard,
+}
+
+impl WorkerError {
+    pub fn code(&self) -> u8 {
+        match *self {
+            WorkerError::Decode => 1,
+            WorkerError::AlreadyInitialized => 2,
+            WorkerError::NotInitialized => 3,
+            WorkerError::WrongWorker => 4,
+            WorkerError::NoSuchBoard => 5,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<WorkerError> {
+        match code {
+            1 => Some(WorkerError::Decode),
+            2 => Some(WorkerError::AlreadyInitialized),
+            3 => Some(WorkerError::NotInitialized),
+            4 => Some(WorkerError::WrongWorker),
+            5 => Some(WorkerError::NoSuchBoard),
+            _ => None,
         }
     }
 }
 
+impl fmt::Display for WorkerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WorkerError::Decode => write!(f, "undecodable event"),
+            WorkerError::AlreadyInitialized => write!(f, "worker already initialized"),
+            WorkerError::NotInitialized => write!(f, "worker not initialized"),
+            WorkerError::WrongWorker => write!(f, "event for another worker"),
+            WorkerError::NoSuchBoard => write!(f, "no such board"),
+        }
+    }
+}
+
+//
+// A board message carries either the full state (block, grid and scheme)
+// or a delta against the previous message of the same board, see
+// DeltaEncoder.
+//
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Msg {
     pub event: AppEvent,
     pub block: Option<Block>,
     pub grid: Option<Grid>,
     pub scheme: Option<Scheme>,
+    pub seq: u32,
+    pub delta: Option<Delta>,
+    #[serde(default)]
+    pub stats: Option<Stats>,
+    #[serde(default)]
+    pub game_over: bool,
+    #[serde(default)]
+    pub clearing: Option<LineClear>,
+    #[serde(default)]
+    pub hold: Option<BlockType>,
+    // Only for a board played for a goal.
+    #[serde(default)]
+    pub mode: Option<ModeState>,
 }
 
 impl Msg {
@@ -670,6 +1575,13 @@ impl Msg {
             block: block,
             grid: grid,
             scheme: scheme,
+            seq: 0,
+            delta: None,
+            stats: None,
+            game_over: false,
+            clearing: None,
+            hold: None,
+            mode: None,
         }
     }
 
@@ -678,7 +1590,7 @@ impl Msg {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Point {
     x: i32,
     y: i32,
@@ -740,7 +1652,7 @@ mod tests {
     #[test]
     fn grid_fill() {
         let mut grid = Grid::new();
-        let block = Block::new(BlockType::J);
+        let block = Block::new(BlockType::J, &Scheme::Type1);
         grid.fill(&block);
 
         let data = grid.get_data();
@@ -751,7 +1663,7 @@ mod tests {
     #[test]
     fn grid_remove() {
         let mut grid = Grid::new();
-        let mut block = Block::new(BlockType::I);
+        let mut block = Block::new(BlockType::I, &Scheme::Type1);
 
         block.down(|_| false);
         grid.fill(&block);
@@ -789,29 +1701,29 @@ mod tests {
     fn grid_erase1() {
         let mut grid = Grid::new();
 
-        let mut block = Block::new(BlockType::Z);
-        block.drop(|_| false);
+        let mut block = Block::new(BlockType::Z, &Scheme::Type1);
+        block.drop(ROWS, |_| false);
         grid.fill(&block);
 
-        let mut block = Block::new(BlockType::Z);
+        let mut block = Block::new(BlockType::Z, &Scheme::Type1);
         right(&mut block, 2);
-        block.drop(|_| false);
+        block.drop(ROWS, |_| false);
         grid.fill(&block);
 
-        let mut block = Block::new(BlockType::Z);
+        let mut block = Block::new(BlockType::Z, &Scheme::Type1);
         right(&mut block, 4);
-        block.drop(|_| false);
+        block.drop(ROWS, |_| false);
         grid.fill(&block);
 
-        let mut block = Block::new(BlockType::Z);
+        let mut block = Block::new(BlockType::Z, &Scheme::Type1);
         right(&mut block, 6);
-        block.drop(|_| false);
+        block.drop(ROWS, |_| false);
         grid.fill(&block);
 
-        let mut block = Block::new(BlockType::T);
+        let mut block = Block::new(BlockType::T, &Scheme::Type1);
         block.rotate();
         right(&mut block, 8);
-        block.drop(|_| false);
+        block.drop(ROWS, |_| false);
         grid.fill(&block);
 
         let data = grid.get_data().clone();
@@ -847,55 +1759,55 @@ mod tests {
     fn grid_erase2() {
         let mut grid = Grid::new();
 
-        let mut block = Block::new(BlockType::I);
-        block.drop(|p| !grid.is_empty(p));
+        let mut block = Block::new(BlockType::I, &Scheme::Type1);
+        block.drop(ROWS, |p| !grid.is_empty(p));
         grid.fill(&block);
 
-        let mut block = Block::new(BlockType::I);
+        let mut block = Block::new(BlockType::I, &Scheme::Type1);
         right(&mut block, 4);
-        block.drop(|p| !grid.is_empty(p));
+        block.drop(ROWS, |p| !grid.is_empty(p));
         grid.fill(&block);
 
-        let mut block = Block::new(BlockType::I);
+        let mut block = Block::new(BlockType::I, &Scheme::Type1);
         right(&mut block, 1);
-        block.drop(|p| !grid.is_empty(p));
+        block.drop(ROWS, |p| !grid.is_empty(p));
         grid.fill(&block);
 
-        let mut block = Block::new(BlockType::I);
+        let mut block = Block::new(BlockType::I, &Scheme::Type1);
         right(&mut block, 5);
-        block.drop(|p| !grid.is_empty(p));
+        block.drop(ROWS, |p| !grid.is_empty(p));
         grid.fill(&block);
 
-        let mut block = Block::new(BlockType::I);
+        let mut block = Block::new(BlockType::I, &Scheme::Type1);
         right(&mut block, 1);
-        block.drop(|p| !grid.is_empty(p));
+        block.drop(ROWS, |p| !grid.is_empty(p));
         grid.fill(&block);
 
-        let mut block = Block::new(BlockType::L);
+        let mut block = Block::new(BlockType::L, &Scheme::Type1);
         right(&mut block, 6);
-        block.drop(|p| !grid.is_empty(p));
+        block.drop(ROWS, |p| !grid.is_empty(p));
         grid.fill(&block);
 
-        let mut block = Block::new(BlockType::I);
+        let mut block = Block::new(BlockType::I, &Scheme::Type1);
         right(&mut block, 1);
-        block.drop(|p| !grid.is_empty(p));
+        block.drop(ROWS, |p| !grid.is_empty(p));
         grid.fill(&block);
 
-        let mut block = Block::new(BlockType::L);
+        let mut block = Block::new(BlockType::L, &Scheme::Type1);
         right(&mut block, 5);
-        block.drop(|p| !grid.is_empty(p));
+        block.drop(ROWS, |p| !grid.is_empty(p));
         grid.fill(&block);
 
-        let mut block = Block::new(BlockType::I);
+        let mut block = Block::new(BlockType::I, &Scheme::Type1);
         block.rotate();
         right(&mut block, 7);
-        block.drop(|p| !grid.is_empty(p));
+        block.drop(ROWS, |p| !grid.is_empty(p));
         grid.fill(&block);
 
-        let mut block = Block::new(BlockType::I);
+        let mut block = Block::new(BlockType::I, &Scheme::Type1);
         block.rotate();
         left(&mut block, 2);
-        block.drop(|p| !grid.is_empty(p));
+        block.drop(ROWS, |p| !grid.is_empty(p));
         grid.fill(&block);
 
         let data = grid.get_data().clone();
@@ -938,4 +1850,361 @@ mod tests {
 
     }
 
+    #[test]
+    fn snapshot_restore() {
+        let mut tetris = Tetris::with_seed(11);
+        tetris.init();
+        for frame in 0..400 {
+            tetris.tick();
+            if frame % 37 == 0 {
+                let side = if frame % 2 == 0 {
+                    BlockEvent::Right
+                } else {
+                    BlockEvent::Left
+                };
+                tetris.event(Some(vec![side, BlockEvent::Drop]));
+            }
+        }
+        assert!(!tetris.is_game_over());
+        assert_eq!(tetris.stats.frames, 400);
+        assert!(tetris.stats.pieces > 0);
+
+        let json = tetris.snapshot().to_json().unwrap();
+        let mut restored = Tetris::from_snapshot(Snapshot::from_json(&json).unwrap()).unwrap();
+
+        for _ in 0..200 {
+            tetris.tick();
+            restored.tick();
+        }
+        assert_eq!(restored.grid.as_slice(), tetris.grid.as_slice());
+        assert_eq!(restored.block.points(), tetris.block.points());
+        assert_eq!(restored.block.next_type(), tetris.block.next_type());
+        assert_eq!(restored.stats.pieces, tetris.stats.pieces);
+
+        // Restoring keeps the grid buffer where it is.
+        let cells = restored.grid.as_slice().as_ptr();
+        restored.restore(tetris.snapshot()).unwrap();
+        assert_eq!(restored.grid.as_slice().as_ptr(), cells);
+
+        let mut snapshot = tetris.snapshot();
+        snapshot.version = 0;
+        assert_eq!(restored.restore(snapshot).err(), Some(SnapshotError::Version(0)));
+
+        // A grid that does not match its size is refused before it is used.
+        let mut snapshot = tetris.snapshot();
+        snapshot.grid.data.truncate(10);
+        let json = snapshot.to_json().unwrap();
+        let truncated = Snapshot::from_json(&json).unwrap();
+        assert_eq!(Tetris::from_snapshot(truncated).err(), Some(SnapshotError::Cells(10)));
+
+        let mut snapshot = tetris.snapshot();
+        snapshot.grid.width = 1000;
+        assert_eq!(
+            restored.restore(snapshot).err(),
+            Some(SnapshotError::Size(SizeError::Columns(1000)))
+        );
+        assert_eq!(restored.grid.as_slice(), tetris.grid.as_slice());
+    }
+
+    #[test]
+    fn undo_redo_placements() {
+        let mut tetris = Tetris::with_seed(5);
+        tetris.init();
+        let empty = tetris.grid.as_slice().to_vec();
+        let first = tetris.block.points();
+        let cells = tetris.grid.as_slice().as_ptr();
+
+        tetris.event(Some(vec![BlockEvent::Drop]));
+        let one = tetris.grid.as_slice().to_vec();
+        tetris.event(Some(vec![BlockEvent::Left, BlockEvent::Drop]));
+        let two = tetris.grid.as_slice().to_vec();
+        assert_eq!(tetris.stats.pieces, 2);
+
+        tetris.event(Some(vec![BlockEvent::Undo, BlockEvent::Undo]));
+        assert_eq!(tetris.grid.as_slice(), &empty[..]);
+        assert_eq!(tetris.block.points(), first);
+        assert_eq!(tetris.stats.pieces, 0);
+        assert!(!tetris.undo());
+
+        tetris.event(Some(vec![BlockEvent::Redo]));
+        assert_eq!(tetris.grid.as_slice(), &one[..]);
+        tetris.event(Some(vec![BlockEvent::Redo]));
+        assert_eq!(tetris.grid.as_slice(), &two[..]);
+        assert!(!tetris.redo());
+        // Views of the grid, like the numpy board, stay valid.
+        assert_eq!(tetris.grid.as_slice().as_ptr(), cells);
+
+        tetris.undo();
+        tetris.event(Some(vec![BlockEvent::Right, BlockEvent::Drop]));
+        assert!(!tetris.redo());
+
+        tetris.set_undo_limit(0);
+        tetris.event(Some(vec![BlockEvent::Drop]));
+        assert!(!tetris.undo());
+    }
+
+    #[test]
+    fn stacking_up_ends_the_game() {
+        let mut tetris = Tetris::with_seed(3);
+        tetris.init();
+
+        for _ in 0..100 {
+            if tetris.is_game_over() {
+                break;
+            }
+            tetris.event(Some(vec![BlockEvent::Drop]));
+        }
+        assert!(tetris.is_game_over());
+
+        let stats = tetris.stats.clone();
+        let grid = tetris.grid.as_slice().to_vec();
+        tetris.tick();
+        tetris.event(Some(vec![BlockEvent::Left, BlockEvent::Drop]));
+        assert_eq!(tetris.stats, stats);
+        assert_eq!(tetris.grid.as_slice(), &grid[..]);
+
+        // Undo takes the last placement back, and the game goes on.
+        tetris.event(Some(vec![BlockEvent::Undo]));
+        assert!(!tetris.is_game_over());
+    }
+
+    #[test]
+    fn line_clears_score_by_level() {
+        let classic = Ruleset::default();
+        let mut stats = Stats::default();
+        stats.add_lines(1, &classic);
+        stats.add_lines(4, &classic);
+        assert_eq!((stats.lines, stats.score, stats.level()), (5, 900, 1));
+
+        stats.add_lines(4, &classic);
+        stats.add_lines(1, &classic);
+        assert_eq!(stats.level(), 2);
+
+        stats.add_lines(2, &classic);
+        assert_eq!(stats.score, 900 + 800 + 100 + 300 * 2);
+
+        let mut stats = Stats::default();
+        stats.add_lines(4, &Ruleset::preset(Preset::Nes));
+        assert_eq!(stats.score, 1200);
+    }
+
+    #[test]
+    fn full_rows_wait_for_the_line_clear_delay() {
+        let mut tetris = Tetris::with_seed(9);
+        tetris.init();
+        tetris.ruleset.line_clear_delay = 3;
+
+        // Fill the bottom row around where the block lands.
+        let mut landing = tetris.block.clone();
+        landing.drop(ROWS, |_| false);
+        let bottom = ROWS as i32 - 1;
+        for x in 0..COLUMNS {
+            let covered = landing
+                .points_ref()
+                .iter()
+                .any(|point| point.x() == x as i32 && point.y() == bottom);
+            if !covered {
+                tetris.grid.set(x, ROWS - 1, 7);
+            }
+        }
+
+        tetris.event(Some(vec![BlockEvent::Drop]));
+        assert_eq!(tetris.stats.lines, 1);
+        assert_eq!(tetris.clearing().unwrap().rows, vec![bottom as u8]);
+        assert!(!tetris.grid.as_slice()[(ROWS - 1) * COLUMNS..].contains(&0));
+
+        // The locked block stays put meanwhile.
+        let points = tetris.block.points();
+        tetris.event(Some(vec![BlockEvent::Left]));
+        tetris.tick();
+        tetris.tick();
+        assert_eq!(tetris.block.points(), points);
+        assert!(tetris.clearing().is_some());
+
+        tetris.tick();
+        assert!(tetris.clearing().is_none());
+        assert!(tetris.block.points() != points);
+        assert_eq!(tetris.stats.pieces, 1);
+    }
+
+    #[test]
+    fn entry_delay_with_initial_rotation_and_hold() {
+        let mut tetris = Tetris::with_seed(5);
+        tetris.init();
+        tetris.ruleset.spawn_delay = 4;
+
+        // Classic rules have no hold.
+        let first = tetris.block.type_ref().clone();
+        tetris.event(Some(vec![BlockEvent::Hold]));
+        assert_eq!(tetris.hold(), None);
+        assert_eq!(tetris.block.type_ref(), &first);
+
+        tetris.ruleset.hold = true;
+        tetris.event(Some(vec![BlockEvent::Hold]));
+        assert_eq!(tetris.hold(), Some(&first));
+
+        // Once per block.
+        let second = tetris.block.type_ref().clone();
+        tetris.event(Some(vec![BlockEvent::Hold]));
+        assert_eq!(tetris.block.type_ref(), &second);
+
+        tetris.event(Some(vec![BlockEvent::Drop]));
+        assert!(tetris.is_waiting());
+        assert!(tetris.get_block().points_ref().is_empty());
+        let next = tetris.block.next_type().unwrap();
+
+        tetris.event(Some(vec![BlockEvent::Hold, BlockEvent::Rotate, BlockEvent::Left]));
+        for _ in 0..3 {
+            tetris.tick();
+        }
+        assert!(tetris.is_waiting());
+        tetris.tick();
+        assert!(!tetris.is_waiting());
+
+        // The held block came back turned, the one that spawned is held.
+        let mut expected = Block::new(first.clone(), &tetris.scheme);
+        expected.align_to_start(COLUMNS);
+        if first != BlockType::O {
+            expected.rotate();
+        }
+        expected.adjust_bound(COLUMNS, ROWS);
+        assert_eq!(tetris.block.points(), expected.points());
+        assert_eq!(tetris.hold(), Some(&next));
+    }
+
+    #[test]
+    fn lock_delay_waits_on_the_ground() {
+        let mut ruleset = Ruleset::preset(Preset::Guideline);
+        ruleset.spawn_delay = 0;
+        ruleset.gravity = vec![1];
+        let mut tetris = Tetris::with_ruleset(4, ruleset);
+        tetris.init();
+
+        while tetris.grid.is_empty_below(tetris.block.points_ref()) {
+            tetris.tick();
+        }
+        // Landing was the first frame on the ground.
+        let grounded = tetris.block.points();
+        for _ in 1..30 {
+            tetris.tick();
+        }
+        assert_eq!(tetris.block.points(), grounded);
+        assert_eq!(tetris.stats.pieces, 0);
+        tetris.tick();
+        assert_eq!(tetris.stats.pieces, 1);
+
+        // A drop does not wait.
+        tetris.event(Some(vec![BlockEvent::Drop]));
+        assert_eq!(tetris.stats.pieces, 2);
+    }
+
+    #[test]
+    fn presets_rotate_preview_and_hold() {
+        let mut nes = Tetris::with_ruleset(2, Ruleset::preset(Preset::Nes));
+        nes.init();
+        assert_eq!(nes.previews().len(), 1);
+        let current = nes.block.type_ref().clone();
+        nes.event(Some(vec![BlockEvent::Hold]));
+        assert_eq!(nes.hold(), None);
+        assert_eq!(nes.block.type_ref(), &current);
+
+        // An upright I against the wall: Nintendo rotation does not turn,
+        // SRS kicks it out.
+        for &(preset, turns) in &[(Preset::Nes, false), (Preset::Guideline, true)] {
+            let mut tetris = Tetris::with_ruleset(2, Ruleset::preset(preset));
+            tetris.block = Block::new(BlockType::I, &tetris.scheme);
+            tetris.block.shift(|| (0, 5));
+            tetris.event(Some(vec![BlockEvent::Rotate]));
+            let x = tetris.block.range().x();
+            tetris.block.shift(|| (-x, 0));
+
+            let upright = tetris.block.points();
+            tetris.event(Some(vec![BlockEvent::Rotate]));
+            assert_eq!(tetris.block.points() != upright, turns);
+            assert!(tetris.grid.fits(tetris.block.points_ref()));
+        }
+
+        let mut guideline = Tetris::with_ruleset(2, Ruleset::preset(Preset::Guideline));
+        guideline.init();
+        let mut types: Vec<u8> = guideline.previews().iter().map(|t| t.index()).collect();
+        assert_eq!(types.len(), 5);
+        types.push(guideline.block.type_ref().index());
+        types.sort();
+        types.dedup();
+        assert_eq!(types.len(), 6);
+    }
+
+    #[test]
+    fn board_sizes() {
+        assert_eq!(Grid::with_size(3, ROWS, 0).err(), Some(SizeError::Columns(3)));
+        assert_eq!(Grid::with_size(COLUMNS, 61, 0).err(), Some(SizeError::Rows(61)));
+        assert_eq!(Grid::with_size(COLUMNS, ROWS, 61).err(), Some(SizeError::Buffer(61)));
+
+        // Hidden rows keep their cells and come down with a cleared row.
+        let mut grid = Grid::with_size(4, 4, 2).unwrap();
+        let mut block = Block::new(BlockType::I, &Scheme::Type1);
+        block.shift(|| (0, -1));
+        grid.fill(&block);
+        assert!(grid.as_slice().iter().all(|&cell| cell == 0));
+        for x in 0..4 {
+            grid.set(x, 3, 1);
+        }
+        grid.remove_row(3);
+        assert_eq!(&grid.get_data()[0], &[7, 7, 7, 7]);
+
+        let mut tetris = Tetris::with_seed(8);
+        tetris.grid = Grid::with_size(4, 40, 0).unwrap();
+        tetris.start_recording();
+        tetris.init();
+        for frame in 0..600 {
+            if frame % 7 == 0 {
+                tetris.event(Some(vec![BlockEvent::Rotate, BlockEvent::Right, BlockEvent::Drop]));
+            }
+            tetris.tick();
+            assert!(tetris.block.points_ref().iter().all(|point| point.x() >= 0 && point.x() < 4));
+        }
+        assert!(tetris.stats.pieces > 0);
+
+        let replay = tetris.take_replay().unwrap();
+        let mut player = ReplayPlayer::new(replay).unwrap();
+        player.seek(600);
+        assert_eq!(player.tetris().grid.as_slice(), tetris.grid.as_slice());
+    }
+
+    #[test]
+    fn top_out_against_the_hidden_rows() {
+        // Stacks rows `top` and below, all but the right column, then drops
+        // an upright block onto them, `left` steps to the left.
+        fn drop_on(buffer: usize, top: usize, left: usize) -> Tetris {
+            let mut tetris = Tetris::with_seed(5);
+            tetris.grid = Grid::with_size(COLUMNS, ROWS, buffer).unwrap();
+            tetris.init();
+            for y in top..ROWS {
+                for x in 0..COLUMNS - 1 {
+                    tetris.grid.set(x, y, 1);
+                }
+            }
+            if tetris.block.range().height() == 1 {
+                tetris.event(Some(vec![BlockEvent::Rotate]));
+            }
+            tetris.event(Some(vec![BlockEvent::Left; left]));
+            tetris.event(Some(vec![BlockEvent::Drop]));
+            tetris
+        }
+
+        // Partly above the board: the hidden rows keep the cells.
+        let tetris = drop_on(BUFFER_ROWS, 1, COLUMNS);
+        assert!(!tetris.is_game_over());
+        let hidden = (0..COLUMNS as i32).filter(|&x| tetris.grid._cell(x, -1) > 0).count();
+        assert!(hidden > 0);
+
+        // Under the spawn, the next block has no room: a block out.
+        assert!(drop_on(BUFFER_ROWS, 1, 0).is_game_over());
+
+        // Wholly above the board is a lock out.
+        assert!(drop_on(BUFFER_ROWS, 0, COLUMNS).is_game_over());
+
+        // Without hidden rows, any cell above the board is lost.
+        assert!(drop_on(0, 1, COLUMNS).is_game_over());
+    }
 }
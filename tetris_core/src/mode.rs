@@ -0,0 +1,396 @@
+use std::io::{Read, Write};
+
+use serde_json;
+
+use {Block, BlockEvent, Stats, Tetris, FRAMES_PER_SECOND};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Goal {
+    // Plays until the game is over, as always.
+    Endless,
+    // Sprint: clear this many lines as fast as possible.
+    Lines(u32),
+}
+
+impl Goal {
+    // The wire sends the line count, 0 for Endless.
+    pub fn lines(&self) -> u32 {
+        match *self {
+            Goal::Endless => 0,
+            Goal::Lines(lines) => lines,
+        }
+    }
+
+    pub fn from_lines(lines: u32) -> Goal {
+        match lines {
+            0 => Goal::Endless,
+            lines => Goal::Lines(lines),
+        }
+    }
+}
+
+// Frames as m:ss.cc.
+pub fn clock(frames: u32) -> String {
+    let centis = frames as u64 * 100 / FRAMES_PER_SECOND as u64;
+    format!("{}:{:02}.{:02}", centis / 6000, centis / 100 % 60, centis % 100)
+}
+
+//
+// A finished run. Finesse faults are the inputs spent beyond the fewest
+// that put each block where it locked.
+//
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub goal: Goal,
+    pub frames: u32,
+    pub pieces: u32,
+    pub faults: u32,
+}
+
+impl Record {
+    pub fn seconds(&self) -> f32 {
+        self.frames as f32 / FRAMES_PER_SECOND as f32
+    }
+
+    pub fn time(&self) -> String {
+        clock(self.frames)
+    }
+}
+
+//
+// What a front-end shows of a mode, sent along with the board's stats.
+//
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModeState {
+    pub goal: Goal,
+    pub faults: u32,
+    pub finished: bool,
+}
+
+impl ModeState {
+    // The finished run, its time and pieces taken from `stats`.
+    pub fn record(&self, stats: &Stats) -> Option<Record> {
+        if !self.finished {
+            return None;
+        }
+        Some(Record {
+            goal: self.goal,
+            frames: stats.frames,
+            pieces: stats.pieces,
+            faults: self.faults,
+        })
+    }
+}
+
+//
+// A game played for a goal against the clock, which is the frame count of
+// the stats. Once the goal is reached the game stops, and with it the
+// clock. Undo and redo only work in an endless game.
+//
+pub struct Mode {
+    pub tetris: Tetris,
+    goal: Goal,
+    // The falling block as it spawned, and the inputs spent on it since.
+    spawn: Option<Block>,
+    inputs: u32,
+    pieces: u32,
+    faults: u32,
+    record: Option<Record>,
+}
+
+impl Mode {
+    pub fn new(tetris: Tetris, goal: Goal) -> Mode {
+        let pieces = tetris.stats.pieces;
+        let mut mode = Mode {
+            tetris: tetris,
+            goal: goal,
+            spawn: None,
+            inputs: 0,
+            pieces: pieces,
+            faults: 0,
+            record: None,
+        };
+        mode._track();
+        mode
+    }
+
+    pub fn goal(&self) -> Goal {
+        self.goal
+    }
+
+    pub fn faults(&self) -> u32 {
+        self.faults
+    }
+
+    pub fn record(&self) -> Option<&Record> {
+        self.record.as_ref()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.record.is_some()
+    }
+
+    // None for an endless game, which has nothing to show.
+    pub fn state(&self) -> Option<ModeState> {
+        match self.goal {
+            Goal::Endless => None,
+            goal => Some(ModeState {
+                goal: goal,
+                faults: self.faults,
+                finished: self.is_finished(),
+            }),
+        }
+    }
+
+    pub fn tick(&mut self) {
+        if self.is_finished() {
+            return;
+        }
+        self.tetris.tick();
+        self._track();
+    }
+
+    //
+    // Every turn counts as an input, and sideways moves once for each
+    // direction in a call that moved the block. A key held into a wall or
+    // an instant shift to it counts once.
+    //
+    pub fn event(&mut self, block_events: Option<Vec<BlockEvent>>) {
+        if self.is_finished() {
+            return;
+        }
+        let block_events = match block_events {
+            Some(events) => match self.goal {
+                Goal::Endless => events,
+                _ => events
+                    .into_iter()
+                    .filter(|event| *event != BlockEvent::Undo && *event != BlockEvent::Redo)
+                    .collect(),
+            },
+            None => return,
+        };
+
+        let falling = !self.tetris.is_waiting();
+        let before = self.tetris.get_block();
+        let hold = self.tetris.hold().cloned();
+        let (turns, moves) = count_inputs(&block_events);
+
+        self.tetris.event(Some(block_events));
+
+        if self.tetris.hold().cloned() != hold {
+            self.spawn = None;
+            self.inputs = 0;
+        } else if falling {
+            let after = match self.tetris.last_locked() {
+                Some(locked) if self.tetris.stats.pieces != self.pieces => locked.clone(),
+                _ => self.tetris.get_block(),
+            };
+            self.inputs += turns;
+            if left(&after) != left(&before) {
+                self.inputs += moves;
+            }
+        }
+        self._track();
+    }
+
+    //
+    // Settles the faults of a block that locked, picks up the next one once
+    // it spawns and stops the game at the goal.
+    //
+    fn _track(&mut self) {
+        if self.tetris.stats.pieces != self.pieces {
+            self.pieces = self.tetris.stats.pieces;
+            if let (Some(spawn), Some(locked)) = (self.spawn.take(), self.tetris.last_locked()) {
+                self.faults += self.inputs.saturating_sub(finesse(&spawn, locked));
+            }
+            self.inputs = 0;
+        }
+
+        if self.spawn.is_none() && !self.tetris.is_waiting() {
+            self.spawn = Some(self.tetris.get_block());
+        }
+
+        if let Goal::Lines(lines) = self.goal {
+            if self.record.is_none() && self.tetris.stats.lines >= lines {
+                self.record = Some(Record {
+                    goal: self.goal,
+                    frames: self.tetris.stats.frames,
+                    pieces: self.tetris.stats.pieces,
+                    faults: self.faults,
+                });
+            }
+        }
+    }
+}
+
+// Turns, then runs of one sideways direction, up to the first drop.
+fn count_inputs(block_events: &[BlockEvent]) -> (u32, u32) {
+    let mut turns = 0;
+    let mut moves = 0;
+    let mut last = None;
+    for event in block_events {
+        match *event {
+            BlockEvent::Rotate => turns += 1,
+            BlockEvent::Left | BlockEvent::Right if last != Some(event) => moves += 1,
+            BlockEvent::Drop => break,
+            _ => (),
+        }
+        if *event == BlockEvent::Left || *event == BlockEvent::Right {
+            last = Some(event);
+        }
+    }
+    (turns, moves)
+}
+
+fn left(block: &Block) -> Option<i32> {
+    block.points_ref().iter().map(|point| point.x()).min()
+}
+
+// The cells relative to the top left corner, in order.
+fn shape(block: &Block) -> Vec<(i32, i32)> {
+    let x = left(block).unwrap_or(0);
+    let y = block.points_ref().iter().map(|point| point.y()).min().unwrap_or(0);
+    let mut cells: Vec<(i32, i32)> =
+        block.points_ref().iter().map(|point| (point.x() - x, point.y() - y)).collect();
+    cells.sort();
+    cells
+}
+
+//
+// The fewest inputs from `spawn` to the column and shape of `locked` on an
+// empty board: the turns first, then one move per column.
+//
+fn finesse(spawn: &Block, locked: &Block) -> u32 {
+    let target = shape(locked);
+    let mut block = spawn.clone();
+    let mut fewest = None;
+    for turns in 0..4 {
+        if shape(&block) == target {
+            let columns = (left(locked).unwrap_or(0) - left(&block).unwrap_or(0)).unsigned_abs();
+            let inputs = turns + columns;
+            fewest = Some(fewest.map_or(inputs, |fewest: u32| fewest.min(inputs)));
+        }
+        block.rotate();
+    }
+    fewest.unwrap_or(0)
+}
+
+//
+// Personal bests: the fastest run for each goal, kept on disk as JSON.
+//
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Bests {
+    records: Vec<Record>,
+}
+
+impl Bests {
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Bests, serde_json::Error> {
+        serde_json::from_reader(reader)
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), serde_json::Error> {
+        serde_json::to_writer_pretty(writer, self)
+    }
+
+    pub fn best(&self, goal: Goal) -> Option<&Record> {
+        self.records.iter().find(|record| record.goal == goal)
+    }
+
+    // Keeps `record` if it is faster than the best for its goal.
+    pub fn add(&mut self, record: Record) -> bool {
+        match self.records.iter().position(|best| best.goal == record.goal) {
+            Some(index) if self.records[index].frames <= record.frames => false,
+            Some(index) => {
+                self.records[index] = record;
+                true
+            }
+            None => {
+                self.records.push(record);
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {COLUMNS, ROWS};
+
+    #[test]
+    fn sprint_stops_at_the_goal() {
+        let mut tetris = Tetris::with_seed(3);
+        tetris.init();
+        let mut mode = Mode::new(tetris, Goal::Lines(1));
+
+        // The bottom row, full but under the lowest cells of the block.
+        let bottom = mode.tetris.block.points_ref().iter().map(|point| point.y()).max().unwrap();
+        for x in 0..COLUMNS {
+            let under = mode.tetris.block.points_ref().iter().any(|point| {
+                point.x() == x as i32 && point.y() == bottom
+            });
+            if !under {
+                mode.tetris.grid.set(x, ROWS - 1, 1);
+            }
+        }
+        for _ in 0..10 {
+            mode.tick();
+        }
+        assert!(!mode.is_finished());
+        mode.event(Some(vec![BlockEvent::Drop]));
+        assert!(mode.is_finished());
+
+        let record = mode.record().unwrap().clone();
+        assert_eq!(record.frames, mode.tetris.stats.frames);
+        assert_eq!(record.pieces, mode.tetris.stats.pieces);
+        assert_eq!(mode.state().unwrap().record(&mode.tetris.stats), Some(record.clone()));
+
+        // The clock stops.
+        mode.tick();
+        mode.event(Some(vec![BlockEvent::Drop]));
+        assert_eq!(mode.tetris.stats.frames, record.frames);
+        assert_eq!(clock(3 * 60 * 60 + 30), "3:00.50");
+    }
+
+    #[test]
+    fn finesse_faults() {
+        let mut tetris = Tetris::with_seed(3);
+        tetris.init();
+        let mut mode = Mode::new(tetris, Goal::Lines(40));
+
+        // One step left is one input.
+        mode.event(Some(vec![BlockEvent::Left]));
+        mode.event(Some(vec![BlockEvent::Drop]));
+        assert_eq!(mode.faults(), 0);
+
+        // Left and back is two wasted.
+        mode.event(Some(vec![BlockEvent::Left]));
+        mode.event(Some(vec![BlockEvent::Right]));
+        mode.event(Some(vec![BlockEvent::Drop]));
+        assert_eq!(mode.faults(), 2);
+
+        // Undo does not take it back.
+        mode.event(Some(vec![BlockEvent::Undo]));
+        assert_eq!(mode.tetris.stats.pieces, 2);
+    }
+
+    #[test]
+    fn bests_keep_the_fastest() {
+        let record = |frames| Record {
+            goal: Goal::Lines(40),
+            frames: frames,
+            pieces: 100,
+            faults: 0,
+        };
+        let mut bests = Bests::default();
+        assert!(bests.add(record(3000)));
+        assert!(!bests.add(record(3100)));
+        assert!(bests.add(record(2900)));
+
+        let mut file = Vec::new();
+        bests.write_to(&mut file).unwrap();
+        let read = Bests::read_from(&mut file.as_slice()).unwrap();
+        assert_eq!(read.best(Goal::Lines(40)), Some(&record(2900)));
+        assert_eq!(read.best(Goal::Lines(20)), None);
+    }
+}
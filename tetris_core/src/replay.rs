@@ -0,0 +1,402 @@
+use std::error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use serde_json;
+
+use {BlockEvent, Grid, Ruleset, Scheme, SizeError, Tetris, COLUMNS, ROWS};
+
+pub const REPLAY_MAGIC: &[u8] = b"TTRP";
+pub const REPLAY_VERSION: u8 = 1;
+
+// Frames between the snapshots a ReplayPlayer keeps for seeking.
+pub const SNAPSHOT_INTERVAL: u32 = 300;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayHeader {
+    pub seed: u64,
+    pub scheme: Scheme,
+    #[serde(default)]
+    pub ruleset: Ruleset,
+    #[serde(default = "default_columns")]
+    pub columns: usize,
+    #[serde(default = "default_rows")]
+    pub rows: usize,
+    #[serde(default)]
+    pub buffer: usize,
+}
+
+fn default_columns() -> usize {
+    COLUMNS
+}
+
+fn default_rows() -> usize {
+    ROWS
+}
+
+impl ReplayHeader {
+    pub fn new(seed: u64, scheme: Scheme) -> ReplayHeader {
+        ReplayHeader {
+            seed: seed,
+            scheme: scheme,
+            ruleset: Ruleset::default(),
+            columns: COLUMNS,
+            rows: ROWS,
+            buffer: 0,
+        }
+    }
+}
+
+//
+// One `Tetris::event()` call. `frame` is the number of ticks played before
+// it, so ticks don't need an entry of their own.
+//
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayInput {
+    pub frame: u32,
+    pub events: Vec<BlockEvent>,
+}
+
+//
+// File layout: REPLAY_MAGIC, one REPLAY_VERSION byte, then the JSON body.
+//
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub header: ReplayHeader,
+    pub frames: u32,
+    pub inputs: Vec<ReplayInput>,
+}
+
+impl Replay {
+    pub fn new(header: ReplayHeader) -> Replay {
+        Replay {
+            header: header,
+            frames: 0,
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        writer.write_all(REPLAY_MAGIC)?;
+        writer.write_all(&[REPLAY_VERSION])?;
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Replay, ReplayError> {
+        let mut magic = [0_u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != REPLAY_MAGIC {
+            return Err(ReplayError::Magic);
+        }
+
+        let mut version = [0_u8; 1];
+        reader.read_exact(&mut version)?;
+        if version[0] != REPLAY_VERSION {
+            return Err(ReplayError::Version(version[0]));
+        }
+
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Magic,
+    Version(u8),
+    // The header's board size is out of the Grid::with_size() limits.
+    Size(SizeError),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReplayError::Io(ref e) => write!(f, "replay io error: {}", e),
+            ReplayError::Json(ref e) => write!(f, "replay body error: {}", e),
+            ReplayError::Magic => write!(f, "not a replay file"),
+            ReplayError::Version(v) => write!(f, "unsupported replay version: {}", v),
+            ReplayError::Size(ref e) => write!(f, "replay board size: {}", e),
+        }
+    }
+}
+
+impl error::Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(e: io::Error) -> ReplayError {
+        ReplayError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ReplayError {
+    fn from(e: serde_json::Error) -> ReplayError {
+        ReplayError::Json(e)
+    }
+}
+
+#[derive(Clone)]
+pub struct Recorder {
+    replay: Replay,
+}
+
+impl Recorder {
+    pub fn new(header: ReplayHeader) -> Recorder {
+        Recorder { replay: Replay::new(header) }
+    }
+
+    pub fn tick(&mut self) {
+        self.replay.frames += 1;
+    }
+
+    pub fn event(&mut self, events: &[BlockEvent]) {
+        if events.is_empty() {
+            return;
+        }
+
+        self.replay.inputs.push(ReplayInput {
+            frame: self.replay.frames,
+            events: events.to_vec(),
+        });
+    }
+
+    pub fn finish(self) -> Replay {
+        self.replay
+    }
+}
+
+//
+// Rebuilds a recorded game frame by frame. Frame `n` is the state after `n`
+// ticks and every input recorded at or before tick `n`.
+//
+pub struct ReplayPlayer {
+    replay: Replay,
+    tetris: Tetris,
+    frame: u32,
+    next_input: usize,
+    snapshots: Vec<(Tetris, usize)>,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Result<ReplayPlayer, ReplayError> {
+        let mut tetris = {
+            let header = &replay.header;
+            let mut tetris = Tetris::with_ruleset(header.seed, header.ruleset.clone());
+            tetris.scheme = header.scheme.clone();
+            tetris.grid = Grid::with_size(header.columns, header.rows, header.buffer)
+                .map_err(ReplayError::Size)?;
+            tetris
+        };
+        // Without undo history the seek copies stay small. Only a replay
+        // that undoes or redoes needs it to play back the same.
+        let undoes = replay.inputs.iter().any(|input| {
+            input.events.iter().any(|event| *event == BlockEvent::Undo || *event == BlockEvent::Redo)
+        });
+        if !undoes {
+            tetris.set_undo_limit(0);
+        }
+        tetris.init();
+
+        let mut player = ReplayPlayer {
+            replay: replay,
+            tetris: tetris,
+            frame: 0,
+            next_input: 0,
+            snapshots: Vec::new(),
+        };
+        player.apply_inputs();
+        player.snapshots.push((player.tetris.clone(), player.next_input));
+        Ok(player)
+    }
+
+    pub fn tetris(&self) -> &Tetris {
+        &self.tetris
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.replay.frames
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.replay.frames
+    }
+
+    fn apply_inputs(&mut self) {
+        while let Some(input) = self.replay.inputs.get(self.next_input) {
+            if input.frame > self.frame {
+                break;
+            }
+
+            self.tetris.event(Some(input.events.clone()));
+            self.next_input += 1;
+        }
+    }
+
+    pub fn step(&mut self) -> bool {
+        if self.is_finished() {
+            return false;
+        }
+
+        self.tetris.tick();
+        self.frame += 1;
+        self.apply_inputs();
+
+        if self.frame.is_multiple_of(SNAPSHOT_INTERVAL) &&
+            self.snapshots.len() as u32 == self.frame / SNAPSHOT_INTERVAL
+        {
+            self.snapshots.push((self.tetris.clone(), self.next_input));
+        }
+        true
+    }
+
+    //
+    // Restores the closest snapshot at or before `frame` unless the current
+    // state is closer, then plays forward to it.
+    //
+    pub fn seek(&mut self, frame: u32) {
+        let frame = frame.min(self.replay.frames);
+        let index = ((frame / SNAPSHOT_INTERVAL) as usize).min(self.snapshots.len() - 1);
+        let snapshot_frame = index as u32 * SNAPSHOT_INTERVAL;
+
+        if frame < self.frame || snapshot_frame > self.frame {
+            let (ref tetris, next_input) = self.snapshots[index];
+            self.tetris = tetris.clone();
+            self.next_input = next_input;
+            self.frame = snapshot_frame;
+        }
+
+        while self.frame < frame {
+            self.step();
+        }
+    }
+
+    pub fn fast_forward(&mut self, frames: u32) {
+        let frame = self.frame.saturating_add(frames);
+        self.seek(frame);
+    }
+
+    pub fn rewind(&mut self, frames: u32) {
+        let frame = self.frame.saturating_sub(frames);
+        self.seek(frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Spreads the pieces over the board so the test games do not top out.
+    fn spread(frame: u32) -> BlockEvent {
+        match (frame / 61) % 3 {
+            0 => BlockEvent::Left,
+            1 => BlockEvent::Right,
+            _ => BlockEvent::Down,
+        }
+    }
+
+    fn play(tetris: &mut Tetris) {
+        tetris.init();
+        for frame in 0..600 {
+            tetris.tick();
+            if frame % 7 == 0 {
+                tetris.event(Some(vec![BlockEvent::Rotate, spread(frame)]));
+            }
+            if frame % 61 == 0 {
+                tetris.event(Some(vec![BlockEvent::Drop]));
+            }
+        }
+        assert!(!tetris.is_game_over());
+    }
+
+    #[test]
+    fn record_and_read_back() {
+        let mut tetris = Tetris::with_seed(7);
+        assert!(tetris.start_recording());
+        play(&mut tetris);
+
+        let replay = tetris.take_replay().unwrap();
+        assert_eq!(replay.header.seed, 7);
+        assert_eq!(replay.frames, 600);
+        assert_eq!(replay.inputs[0].frame, 1);
+        assert_eq!(replay.inputs[1].events, vec![BlockEvent::Drop]);
+
+        let mut file = Vec::new();
+        replay.write_to(&mut file).unwrap();
+        assert_eq!(&file[0..4], REPLAY_MAGIC);
+
+        let read = Replay::read_from(&mut file.as_slice()).unwrap();
+        assert_eq!(read.frames, replay.frames);
+        assert_eq!(read.inputs, replay.inputs);
+    }
+
+    #[test]
+    fn seed_reproduces_game() {
+        let mut a = Tetris::with_seed(42);
+        let mut b = Tetris::with_seed(42);
+        play(&mut a);
+        play(&mut b);
+
+        assert_eq!(a.grid.as_slice(), b.grid.as_slice());
+        assert_eq!(a.block.type_ref(), b.block.type_ref());
+    }
+
+    #[test]
+    fn player_rebuilds_and_seeks() {
+        let mut tetris = Tetris::with_seed(3);
+        tetris.start_recording();
+        play(&mut tetris);
+        let replay = tetris.take_replay().unwrap();
+
+        let mut player = ReplayPlayer::new(replay).unwrap();
+        while player.step() {}
+        assert_eq!(player.frame(), 600);
+        assert_eq!(player.tetris().grid.as_slice(), tetris.grid.as_slice());
+
+        let mut reference = ReplayPlayer::new(player.replay().clone()).unwrap();
+        reference.seek(450);
+
+        player.rewind(150);
+        assert_eq!(player.frame(), 450);
+        assert_eq!(player.tetris().grid.as_slice(), reference.tetris().grid.as_slice());
+        assert_eq!(player.tetris().block.points(), reference.tetris().block.points());
+
+        player.seek(10);
+        player.fast_forward(440);
+        assert_eq!(player.tetris().grid.as_slice(), reference.tetris().grid.as_slice());
+
+        player.fast_forward(10_000);
+        assert!(player.is_finished());
+
+        // A replay that never undoes keeps no undo history for seeking.
+        assert!(player.snapshots.iter().all(|(tetris, _)| tetris.undo.is_empty()));
+
+        let mut replay = player.replay().clone();
+        replay.header.columns = 100;
+        assert!(matches!(
+            ReplayPlayer::new(replay),
+            Err(ReplayError::Size(SizeError::Columns(100)))
+        ));
+    }
+
+    #[test]
+    fn reject_started_game_and_bad_file() {
+        let mut tetris = Tetris::with_seed(1);
+        tetris.init();
+        tetris.tick();
+        assert!(!tetris.start_recording());
+
+        match Replay::read_from(&mut &b"TTRP\x09{}"[..]) {
+            Err(ReplayError::Version(9)) => (),
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use {BlockType, Rng, GRAVITY, LINE_CLEAR_DELAY};
+
+//
+// How a block turns. Classic is this project's own: the block turns about
+// its third cell and is pushed back inside the walls, even into other
+// cells. The others turn only where the block fits, trying the kicks of
+// their table in order.
+//
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Rotation {
+    Classic,
+    // No kicks.
+    Nintendo,
+    // One step right, then one step left.
+    Ars,
+    // The SRS wall kick tables.
+    Srs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Randomizer {
+    // Every type equally likely, every time.
+    Uniform,
+    // The seven types shuffled, dealt, shuffled again.
+    Bag7,
+    // Rolls again once on a repeat.
+    Nes,
+    // Up to four rolls to avoid the last four types.
+    Tgm,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Preset {
+    Classic,
+    Guideline,
+    Nes,
+    Tgm,
+}
+
+impl Preset {
+    pub fn code(&self) -> u8 {
+        match *self {
+            Preset::Classic => 0,
+            Preset::Guideline => 1,
+            Preset::Nes => 2,
+            Preset::Tgm => 3,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Preset> {
+        match code {
+            0 => Some(Preset::Classic),
+            1 => Some(Preset::Guideline),
+            2 => Some(Preset::Nes),
+            3 => Some(Preset::Tgm),
+            _ => None,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Preset> {
+        match name.to_lowercase().as_str() {
+            "classic" => Some(Preset::Classic),
+            "guideline" => Some(Preset::Guideline),
+            "nes" => Some(Preset::Nes),
+            "tgm" => Some(Preset::Tgm),
+            _ => None,
+        }
+    }
+}
+
+// Frames per row by level, from level 1. The last entry holds for the
+// levels after it.
+const GUIDELINE_GRAVITY: &[u32] = &[60, 48, 37, 28, 21, 16, 11, 8, 6, 4, 3, 2, 1];
+const NES_GRAVITY: &[u32] = &[
+    48, 43, 38, 33, 28, 23, 18, 13, 8, 6, 5, 5, 5, 4, 4, 4, 3, 3, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 1,
+];
+const TGM_GRAVITY: &[u32] = &[64, 32, 16, 8, 4, 2, 1];
+
+//
+// The rules a game is played by. Tetris::with_seed plays Classic, the
+// rules this project always had.
+//
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ruleset {
+    pub rotation: Rotation,
+    pub randomizer: Randomizer,
+    // Frames per row by level, see `gravity()`.
+    pub gravity: Vec<u32>,
+    // Frames a grounded block waits before it locks, 0 locks on touch.
+    // A drop always locks at once.
+    pub lock_delay: u32,
+    // Blocks shown ahead, the next one included.
+    pub previews: u8,
+    pub hold: bool,
+    // Points for 1 to 4 rows, times the level.
+    pub line_scores: [u32; 4],
+    // Frames full rows stay before they collapse, 0 clears them at once.
+    pub line_clear_delay: u32,
+    // Entry delay (ARE): frames between a lock, or the end of a line clear,
+    // and the next spawn.
+    pub spawn_delay: u32,
+}
+
+impl Ruleset {
+    pub fn preset(preset: Preset) -> Ruleset {
+        match preset {
+            Preset::Classic => Ruleset {
+                rotation: Rotation::Classic,
+                randomizer: Randomizer::Uniform,
+                gravity: vec![GRAVITY],
+                lock_delay: 0,
+                previews: 1,
+                hold: false,
+                line_scores: [100, 300, 500, 800],
+                line_clear_delay: 0,
+                spawn_delay: 0,
+            },
+            Preset::Guideline => Ruleset {
+                rotation: Rotation::Srs,
+                randomizer: Randomizer::Bag7,
+                gravity: GUIDELINE_GRAVITY.to_vec(),
+                lock_delay: 30,
+                previews: 5,
+                hold: true,
+                line_scores: [100, 300, 500, 800],
+                line_clear_delay: LINE_CLEAR_DELAY,
+                spawn_delay: 6,
+            },
+            Preset::Nes => Ruleset {
+                rotation: Rotation::Nintendo,
+                randomizer: Randomizer::Nes,
+                gravity: NES_GRAVITY.to_vec(),
+                lock_delay: 0,
+                previews: 1,
+                hold: false,
+                line_scores: [40, 100, 300, 1200],
+                line_clear_delay: LINE_CLEAR_DELAY,
+                spawn_delay: 10,
+            },
+            Preset::Tgm => Ruleset {
+                rotation: Rotation::Ars,
+                randomizer: Randomizer::Tgm,
+                gravity: TGM_GRAVITY.to_vec(),
+                lock_delay: 30,
+                previews: 1,
+                hold: false,
+                line_scores: [100, 400, 900, 1600],
+                line_clear_delay: 41,
+                spawn_delay: 30,
+            },
+        }
+    }
+
+    pub fn gravity(&self, level: u32) -> u32 {
+        let index = (level.max(1) - 1) as usize;
+        match self.gravity.get(index).or_else(|| self.gravity.last()) {
+            Some(&frames) => frames.max(1),
+            None => GRAVITY,
+        }
+    }
+
+    pub fn line_score(&self, lines: u32) -> u32 {
+        match lines {
+            0 => 0,
+            n => self.line_scores[n.min(4) as usize - 1],
+        }
+    }
+
+    //
+    // Offsets to try, in order, for a block leaving `orientation` with one
+    // turn. Orientations count clockwise from the spawn and a turn is
+    // counterclockwise. y grows downwards.
+    //
+    pub fn kicks(&self, block_type: &BlockType, orientation: u8) -> Vec<(i32, i32)> {
+        match self.rotation {
+            Rotation::Classic | Rotation::Nintendo => vec![(0, 0)],
+            Rotation::Ars if *block_type == BlockType::I => vec![(0, 0)],
+            Rotation::Ars => vec![(0, 0), (1, 0), (-1, 0)],
+            Rotation::Srs => {
+                let table = if *block_type == BlockType::I {
+                    SRS_I_KICKS
+                } else {
+                    SRS_KICKS
+                };
+                table[orientation as usize % 4]
+                    .iter()
+                    .map(|&(x, y)| (x, -y))
+                    .collect()
+            }
+        }
+    }
+}
+
+impl Default for Ruleset {
+    fn default() -> Ruleset {
+        Ruleset::preset(Preset::Classic)
+    }
+}
+
+// Counterclockwise turns from 0, R, 2 and L, with y growing upwards as in
+// the SRS tables.
+const SRS_KICKS: [&[(i32, i32)]; 4] = [
+    &[(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+    &[(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+    &[(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+    &[(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+];
+const SRS_I_KICKS: [&[(i32, i32)]; 4] = [
+    &[(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+    &[(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+    &[(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+    &[(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+];
+
+const BLOCK_TYPES: [BlockType; 7] = [
+    BlockType::T,
+    BlockType::J,
+    BlockType::L,
+    BlockType::S,
+    BlockType::Z,
+    BlockType::O,
+    BlockType::I,
+];
+
+//
+// What a Randomizer remembers between blocks: the rest of the bag, or the
+// last types dealt.
+//
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Generator {
+    bag: Vec<BlockType>,
+    history: Vec<BlockType>,
+}
+
+impl Generator {
+    pub fn next(&mut self, randomizer: Randomizer, rng: &mut Rng) -> BlockType {
+        match randomizer {
+            Randomizer::Uniform => BlockType::from_rng(rng),
+            Randomizer::Bag7 => {
+                if self.bag.is_empty() {
+                    self.bag = BLOCK_TYPES.to_vec();
+                    for i in (1..self.bag.len()).rev() {
+                        let j = rng.range(0, i as u32 + 1) as usize;
+                        self.bag.swap(i, j);
+                    }
+                }
+                self.bag.pop().unwrap()
+            }
+            Randomizer::Nes => {
+                // 8 stands for the roll NES spends on a reroll.
+                let roll = rng.range(1, 9) as u8;
+                let repeat = self.history.last().map(|last| last.index()) == Some(roll);
+                let block_type = if roll == 8 || repeat {
+                    BlockType::from_rng(rng)
+                } else {
+                    BlockType::new(roll)
+                };
+                self.history = vec![block_type.clone()];
+                block_type
+            }
+            Randomizer::Tgm => {
+                if self.history.is_empty() {
+                    self.history = vec![BlockType::Z, BlockType::S, BlockType::S, BlockType::Z];
+                }
+                let mut block_type = BlockType::from_rng(rng);
+                for _ in 1..4 {
+                    if !self.history.contains(&block_type) {
+                        break;
+                    }
+                    block_type = BlockType::from_rng(rng);
+                }
+                self.history.remove(0);
+                self.history.push(block_type.clone());
+                block_type
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bag_deals_every_type_once() {
+        let mut rng = Rng::new(7);
+        let mut generator = Generator::default();
+        for _ in 0..3 {
+            let mut indexes: Vec<u8> = (0..7)
+                .map(|_| generator.next(Randomizer::Bag7, &mut rng).index())
+                .collect();
+            indexes.sort();
+            assert_eq!(indexes, vec![1, 2, 3, 4, 5, 6, 7]);
+        }
+    }
+
+    #[test]
+    fn uniform_matches_the_classic_draw() {
+        let mut rng = Rng::new(3);
+        let mut classic = Rng::new(3);
+        let mut generator = Generator::default();
+        for _ in 0..20 {
+            assert_eq!(
+                generator.next(Randomizer::Uniform, &mut rng),
+                BlockType::from_rng(&mut classic)
+            );
+        }
+    }
+
+    #[test]
+    fn gravity_and_scores_by_level() {
+        let nes = Ruleset::preset(Preset::Nes);
+        assert_eq!(nes.gravity(1), 48);
+        assert_eq!(nes.gravity(100), 1);
+        assert_eq!(nes.line_score(4), 1200);
+        assert_eq!(Ruleset::default().gravity(5), GRAVITY);
+        assert_eq!(Ruleset::default().line_score(2), 300);
+
+        let srs = Ruleset::preset(Preset::Guideline);
+        assert_eq!(srs.kicks(&BlockType::T, 0)[1], (1, 0));
+        assert_eq!(srs.kicks(&BlockType::T, 0)[2], (1, -1));
+    }
+}
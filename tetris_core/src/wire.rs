@@ -0,0 +1,807 @@
+//
+// Encodings of AppEvent and Msg between the main thread and the workers.
+//
+// Binary messages start with WIRE_VERSION, which can never start a JSON
+// message, so the receiver picks the decoder from the first byte and JSON
+// stays usable for debugging. Integers are little endian. Grid cells are
+// packed two per byte, low nibble first.
+//
+// Workers always answer with a list of messages, see encode_msgs().
+//
+// A goal goes as its line count, 0 for Endless.
+//
+use std::error;
+use std::fmt;
+use std::str;
+
+use serde_json;
+
+use {AppEvent, Block, BlockEvent, BlockType, BlockUpdate, Delta, Goal, Grid, LineClear, ModeState, Msg, Point,
+     Preset, Scheme, Stats, WorkerError};
+
+//
+// Bumped with every change to the layout:
+// 0xB1 first layout
+// 0xB2 stats and game over in Msg
+// 0xB3 line clears in Msg
+// 0xB4 hold piece in Msg, Hold event
+// 0xB5 preset in InitTetris
+// 0xB6 goal in InitTetris, mode in Msg
+// 0xB7 u16 block event counts
+// 0xB8 UserEach
+//
+pub const WIRE_VERSION: u8 = 0xB8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Codec {
+    Json,
+    Binary,
+}
+
+impl Codec {
+    pub fn of(data: &[u8]) -> Codec {
+        match data.first() {
+            Some(&WIRE_VERSION) => Codec::Binary,
+            _ => Codec::Json,
+        }
+    }
+
+    pub fn encode_event(&self, event: &AppEvent) -> Vec<u8> {
+        match *self {
+            Codec::Json => serde_json::to_vec(event).expect("Serialize error"),
+            Codec::Binary => {
+                let mut w = Writer::new();
+                w.event(event);
+                w.buf
+            }
+        }
+    }
+
+    pub fn encode_msg(&self, msg: &Msg) -> Vec<u8> {
+        match *self {
+            Codec::Json => serde_json::to_vec(msg).expect("Serialize error"),
+            Codec::Binary => {
+                let mut w = Writer::new();
+                w.msg(msg);
+                w.buf
+            }
+        }
+    }
+
+    pub fn encode_msgs(&self, msgs: &[Msg]) -> Vec<u8> {
+        match *self {
+            Codec::Json => serde_json::to_vec(msgs).expect("Serialize error"),
+            Codec::Binary => {
+                let mut w = Writer::new();
+                w.u16(msgs.len() as u16);
+                for msg in msgs {
+                    w.msg(msg);
+                }
+                w.buf
+            }
+        }
+    }
+}
+
+pub fn decode_event(data: &[u8]) -> Result<AppEvent, WireError> {
+    match Codec::of(data) {
+        Codec::Json => Ok(serde_json::from_str(str::from_utf8(data)?)?),
+        Codec::Binary => {
+            let mut r = Reader::new(data);
+            r.version()?;
+            r.event()
+        }
+    }
+}
+
+pub fn decode_msg(data: &[u8]) -> Result<Msg, WireError> {
+    match Codec::of(data) {
+        Codec::Json => Ok(serde_json::from_str(str::from_utf8(data)?)?),
+        Codec::Binary => {
+            let mut r = Reader::new(data);
+            r.version()?;
+            r.msg()
+        }
+    }
+}
+
+pub fn decode_msgs(data: &[u8]) -> Result<Vec<Msg>, WireError> {
+    match Codec::of(data) {
+        Codec::Json => Ok(serde_json::from_str(str::from_utf8(data)?)?),
+        Codec::Binary => {
+            let mut r = Reader::new(data);
+            r.version()?;
+            let count = r.u16()?;
+            let mut msgs = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                msgs.push(r.msg()?);
+            }
+            Ok(msgs)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum WireError {
+    Utf8(str::Utf8Error),
+    Json(serde_json::Error),
+    Version(u8),
+    Truncated,
+    Tag(u8),
+    GridSize(usize, usize),
+    // An error inside an error, or a next block with its own next.
+    Nested,
+}
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WireError::Utf8(ref e) => write!(f, "invalid utf-8: {}", e),
+            WireError::Json(ref e) => write!(f, "invalid json: {}", e),
+            WireError::Version(v) => write!(f, "unknown wire version: {}", v),
+            WireError::Truncated => write!(f, "truncated message"),
+            WireError::Tag(t) => write!(f, "unknown tag: {}", t),
+            WireError::GridSize(w, h) => write!(f, "unexpected grid size: {}x{}", w, h),
+            WireError::Nested => write!(f, "nested too deep"),
+        }
+    }
+}
+
+impl error::Error for WireError {}
+
+impl From<str::Utf8Error> for WireError {
+    fn from(e: str::Utf8Error) -> WireError {
+        WireError::Utf8(e)
+    }
+}
+
+impl From<serde_json::Error> for WireError {
+    fn from(e: serde_json::Error) -> WireError {
+        WireError::Json(e)
+    }
+}
+
+const EVENT_INIT_WORKER: u8 = 0;
+const EVENT_INIT_TETRIS: u8 = 1;
+const EVENT_TICK: u8 = 2;
+const EVENT_USER: u8 = 3;
+const EVENT_RESYNC: u8 = 4;
+const EVENT_TICK_ALL: u8 = 5;
+const EVENT_USER_ALL: u8 = 6;
+const EVENT_ERROR: u8 = 7;
+const EVENT_USER_EACH: u8 = 8;
+
+const HAS_BLOCK: u8 = 1;
+const HAS_GRID: u8 = 1 << 1;
+const HAS_SCHEME: u8 = 1 << 2;
+const HAS_DELTA: u8 = 1 << 3;
+const HAS_STATS: u8 = 1 << 4;
+const GAME_OVER: u8 = 1 << 5;
+const HAS_CLEAR: u8 = 1 << 6;
+const HAS_HOLD: u8 = 1 << 7;
+
+const UPDATE_NONE: u8 = 0;
+const UPDATE_MOVE: u8 = 1;
+const UPDATE_SPAWN: u8 = 2;
+
+fn block_event_code(event: &BlockEvent) -> u8 {
+    match *event {
+        BlockEvent::Left => 0,
+        BlockEvent::Right => 1,
+        BlockEvent::Down => 2,
+        BlockEvent::Drop => 3,
+        BlockEvent::Rotate => 4,
+        BlockEvent::Undo => 5,
+        BlockEvent::Redo => 6,
+        BlockEvent::None => 7,
+        BlockEvent::Hold => 8,
+    }
+}
+
+fn block_event(code: u8) -> Result<BlockEvent, WireError> {
+    Ok(match code {
+        0 => BlockEvent::Left,
+        1 => BlockEvent::Right,
+        2 => BlockEvent::Down,
+        3 => BlockEvent::Drop,
+        4 => BlockEvent::Rotate,
+        5 => BlockEvent::Undo,
+        6 => BlockEvent::Redo,
+        7 => BlockEvent::None,
+        8 => BlockEvent::Hold,
+        _ => return Err(WireError::Tag(code)),
+    })
+}
+
+fn scheme_code(scheme: &Scheme) -> u8 {
+    match *scheme {
+        Scheme::Type1 => 0,
+        Scheme::Type2 => 1,
+        Scheme::Type3 => 2,
+        Scheme::Type4 => 3,
+        Scheme::Type5 => 4,
+    }
+}
+
+fn scheme(code: u8) -> Result<Scheme, WireError> {
+    Ok(match code {
+        0 => Scheme::Type1,
+        1 => Scheme::Type2,
+        2 => Scheme::Type3,
+        3 => Scheme::Type4,
+        4 => Scheme::Type5,
+        _ => return Err(WireError::Tag(code)),
+    })
+}
+
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn new() -> Writer {
+        Writer { buf: vec![WIRE_VERSION] }
+    }
+
+    fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    fn u16(&mut self, v: u16) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn event(&mut self, event: &AppEvent) {
+        let (tag, worker, id) = match *event {
+            AppEvent::InitWorker(w, count) => (EVENT_INIT_WORKER, w, count),
+            AppEvent::InitTetris(w, id, _, _) => (EVENT_INIT_TETRIS, w, id),
+            AppEvent::Tick(w, id) => (EVENT_TICK, w, id),
+            AppEvent::User(w, id, _) => (EVENT_USER, w, id),
+            AppEvent::Resync(w, id) => (EVENT_RESYNC, w, id),
+            AppEvent::TickAll(w) => (EVENT_TICK_ALL, w, 0),
+            AppEvent::UserAll(w, _) => (EVENT_USER_ALL, w, 0),
+            AppEvent::UserEach(w, ref inputs) => (EVENT_USER_EACH, w, inputs.len() as u32),
+            AppEvent::Error(w, error, _) => (EVENT_ERROR, w, error.code() as u32),
+        };
+        self.u8(tag);
+        self.u8(worker);
+        self.u32(id);
+
+        if let AppEvent::InitTetris(_, _, preset, goal) = *event {
+            self.u8(preset.code());
+            self.u32(goal.lines());
+        }
+
+        if let AppEvent::User(_, _, ref events) | AppEvent::UserAll(_, ref events) = *event {
+            match *events {
+                Some(ref events) => {
+                    self.u8(1);
+                    self.block_events(events);
+                }
+                None => self.u8(0),
+            }
+        }
+
+        if let AppEvent::UserEach(_, ref inputs) = *event {
+            for &(id, ref events) in inputs {
+                self.u32(id);
+                self.block_events(events);
+            }
+        }
+
+        if let AppEvent::Error(_, _, ref original) = *event {
+            match *original {
+                Some(ref original) => {
+                    self.u8(1);
+                    self.event(original);
+                }
+                None => self.u8(0),
+            }
+        }
+    }
+
+    fn block_events(&mut self, events: &[BlockEvent]) {
+        self.u16(events.len() as u16);
+        for e in events {
+            self.u8(block_event_code(e));
+        }
+    }
+
+    fn points(&mut self, points: &[Point]) {
+        self.u8(points.len() as u8);
+        for point in points {
+            self.u8(point.x() as i8 as u8);
+            self.u8(point.y() as i8 as u8);
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.u8(block.block_type.index());
+        self.u8(block.color.0);
+        self.u8(block.color.1);
+        self.u8(block.color.2);
+        self.points(&block.points);
+        match block.next {
+            Some(ref next) => {
+                self.u8(1);
+                self.block(next);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn grid(&mut self, grid: &Grid) {
+        self.u8(grid.width() as u8);
+        self.u8(grid.height() as u8);
+        for pair in grid.as_slice().chunks(2) {
+            let high = if pair.len() > 1 { pair[1] } else { 0 };
+            self.u8((pair[0] & 0x0F) | (high << 4));
+        }
+    }
+
+    fn delta(&mut self, delta: &Delta) {
+        self.u16(delta.cells.len() as u16);
+        for &(x, y, value) in &delta.cells {
+            self.u8(x);
+            self.u8(y);
+            self.u8(value);
+        }
+
+        match delta.block {
+            None => self.u8(UPDATE_NONE),
+            Some(BlockUpdate::Move(ref points)) => {
+                self.u8(UPDATE_MOVE);
+                self.points(points);
+            }
+            Some(BlockUpdate::Spawn(ref block)) => {
+                self.u8(UPDATE_SPAWN);
+                self.block(block);
+            }
+        }
+    }
+
+    fn msg(&mut self, msg: &Msg) {
+        self.event(&msg.event);
+        self.u32(msg.seq);
+
+        let mut flags = 0;
+        if msg.block.is_some() {
+            flags |= HAS_BLOCK;
+        }
+        if msg.grid.is_some() {
+            flags |= HAS_GRID;
+        }
+        if msg.scheme.is_some() {
+            flags |= HAS_SCHEME;
+        }
+        if msg.delta.is_some() {
+            flags |= HAS_DELTA;
+        }
+        if msg.stats.is_some() {
+            flags |= HAS_STATS;
+        }
+        if msg.game_over {
+            flags |= GAME_OVER;
+        }
+        if msg.clearing.is_some() {
+            flags |= HAS_CLEAR;
+        }
+        if msg.hold.is_some() {
+            flags |= HAS_HOLD;
+        }
+        self.u8(flags);
+
+        if let Some(ref block) = msg.block {
+            self.block(block);
+        }
+        if let Some(ref grid) = msg.grid {
+            self.grid(grid);
+        }
+        if let Some(ref scheme) = msg.scheme {
+            self.u8(scheme_code(scheme));
+        }
+        if let Some(ref delta) = msg.delta {
+            self.delta(delta);
+        }
+        if let Some(ref stats) = msg.stats {
+            self.u32(stats.frames);
+            self.u32(stats.pieces);
+            self.u32(stats.lines);
+            self.u32(stats.score);
+        }
+        if let Some(ref clearing) = msg.clearing {
+            self.u8(clearing.rows.len() as u8);
+            for &row in &clearing.rows {
+                self.u8(row);
+            }
+            self.u32(clearing.frames);
+            self.u32(clearing.delay);
+        }
+        if let Some(ref hold) = msg.hold {
+            self.u8(hold.index());
+        }
+        match msg.mode {
+            Some(ref mode) => {
+                self.u8(1);
+                self.u32(mode.goal.lines());
+                self.u32(mode.faults);
+                self.u8(mode.finished as u8);
+            }
+            None => self.u8(0),
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data: data, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], WireError> {
+        if self.pos + len > self.data.len() {
+            return Err(WireError::Truncated);
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, WireError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, WireError> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, WireError> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn version(&mut self) -> Result<(), WireError> {
+        match self.u8()? {
+            WIRE_VERSION => Ok(()),
+            v => Err(WireError::Version(v)),
+        }
+    }
+
+    fn event(&mut self) -> Result<AppEvent, WireError> {
+        self._event(false)
+    }
+
+    // An error carries the event that failed, which cannot be an error.
+    fn _event(&mut self, nested: bool) -> Result<AppEvent, WireError> {
+        let tag = self.u8()?;
+        let worker = self.u8()?;
+        let id = self.u32()?;
+
+        Ok(match tag {
+            EVENT_INIT_WORKER => AppEvent::InitWorker(worker, id),
+            EVENT_INIT_TETRIS => {
+                let code = self.u8()?;
+                match Preset::from_code(code) {
+                    Some(preset) => AppEvent::InitTetris(worker, id, preset, Goal::from_lines(self.u32()?)),
+                    None => return Err(WireError::Tag(code)),
+                }
+            }
+            EVENT_TICK => AppEvent::Tick(worker, id),
+            EVENT_USER => AppEvent::User(worker, id, self.block_events()?),
+            EVENT_RESYNC => AppEvent::Resync(worker, id),
+            EVENT_TICK_ALL => AppEvent::TickAll(worker),
+            EVENT_USER_ALL => AppEvent::UserAll(worker, self.block_events()?),
+            EVENT_USER_EACH => {
+                let mut inputs = Vec::with_capacity(id.min(u16::MAX as u32) as usize);
+                for _ in 0..id {
+                    let tetris_id = self.u32()?;
+                    inputs.push((tetris_id, self.events()?));
+                }
+                AppEvent::UserEach(worker, inputs)
+            }
+            EVENT_ERROR => {
+                let error = match WorkerError::from_code(id as u8) {
+                    Some(error) => error,
+                    None => return Err(WireError::Tag(id as u8)),
+                };
+                if nested {
+                    return Err(WireError::Nested);
+                }
+                let original = if self.u8()? == 1 {
+                    Some(Box::new(self._event(true)?))
+                } else {
+                    None
+                };
+                AppEvent::Error(worker, error, original)
+            }
+            _ => return Err(WireError::Tag(tag)),
+        })
+    }
+
+    fn block_events(&mut self) -> Result<Option<Vec<BlockEvent>>, WireError> {
+        if self.u8()? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(self.events()?))
+    }
+
+    fn events(&mut self) -> Result<Vec<BlockEvent>, WireError> {
+        let count = self.u16()?;
+        let mut events = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            events.push(block_event(self.u8()?)?);
+        }
+        Ok(events)
+    }
+
+    fn points(&mut self) -> Result<Vec<Point>, WireError> {
+        let count = self.u8()?;
+        let mut points = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let x = self.u8()? as i8 as i32;
+            let y = self.u8()? as i8 as i32;
+            points.push(Point::new(x, y));
+        }
+        Ok(points)
+    }
+
+    fn block(&mut self) -> Result<Block, WireError> {
+        self._block(false)
+    }
+
+    // The next block goes along, but not the one after it.
+    fn _block(&mut self, nested: bool) -> Result<Block, WireError> {
+        let index = self.u8()?;
+        if !(1..=7).contains(&index) {
+            return Err(WireError::Tag(index));
+        }
+
+        let color = (self.u8()?, self.u8()?, self.u8()?);
+        let points = self.points()?;
+        let next = if self.u8()? == 1 {
+            if nested {
+                return Err(WireError::Nested);
+            }
+            Some(Box::new(self._block(true)?))
+        } else {
+            None
+        };
+
+        Ok(Block {
+            block_type: BlockType::new(index),
+            color: color,
+            points: points,
+            next: next,
+            orientation: 0,
+        })
+    }
+
+    fn grid(&mut self) -> Result<Grid, WireError> {
+        let width = self.u8()? as usize;
+        let height = self.u8()? as usize;
+
+        // Only the visible rows are sent.
+        let mut grid = match Grid::with_size(width, height, 0) {
+            Ok(grid) => grid,
+            Err(_) => return Err(WireError::GridSize(width, height)),
+        };
+
+        let packed = self.take((width * height).div_ceil(2))?;
+        for (i, cell) in grid.data.iter_mut().enumerate() {
+            let byte = packed[i / 2];
+            *cell = if i % 2 == 0 { byte & 0x0F } else { byte >> 4 };
+        }
+        Ok(grid)
+    }
+
+    fn delta(&mut self) -> Result<Delta, WireError> {
+        let count = self.u16()?;
+        let mut cells = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            cells.push((self.u8()?, self.u8()?, self.u8()?));
+        }
+
+        let block = match self.u8()? {
+            UPDATE_NONE => None,
+            UPDATE_MOVE => Some(BlockUpdate::Move(self.points()?)),
+            UPDATE_SPAWN => Some(BlockUpdate::Spawn(self.block()?)),
+            tag => return Err(WireError::Tag(tag)),
+        };
+
+        Ok(Delta {
+            cells: cells,
+            block: block,
+        })
+    }
+
+    fn msg(&mut self) -> Result<Msg, WireError> {
+        let event = self.event()?;
+        let seq = self.u32()?;
+        let flags = self.u8()?;
+
+        let block = if flags & HAS_BLOCK != 0 {
+            Some(self.block()?)
+        } else {
+            None
+        };
+        let grid = if flags & HAS_GRID != 0 {
+            Some(self.grid()?)
+        } else {
+            None
+        };
+        let scheme = if flags & HAS_SCHEME != 0 {
+            Some(scheme(self.u8()?)?)
+        } else {
+            None
+        };
+
+        let mut msg = Msg::new(event, block, grid, scheme);
+        msg.seq = seq;
+        if flags & HAS_DELTA != 0 {
+            msg.delta = Some(self.delta()?);
+        }
+        if flags & HAS_STATS != 0 {
+            msg.stats = Some(Stats {
+                frames: self.u32()?,
+                pieces: self.u32()?,
+                lines: self.u32()?,
+                score: self.u32()?,
+            });
+        }
+        msg.game_over = flags & GAME_OVER != 0;
+        if flags & HAS_CLEAR != 0 {
+            let count = self.u8()?;
+            let mut rows = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                rows.push(self.u8()?);
+            }
+            msg.clearing = Some(LineClear {
+                rows: rows,
+                frames: self.u32()?,
+                delay: self.u32()?,
+            });
+        }
+        if flags & HAS_HOLD != 0 {
+            let index = self.u8()?;
+            if !(1..=7).contains(&index) {
+                return Err(WireError::Tag(index));
+            }
+            msg.hold = Some(BlockType::new(index));
+        }
+        if self.u8()? != 0 {
+            msg.mode = Some(ModeState {
+                goal: Goal::from_lines(self.u32()?),
+                faults: self.u32()?,
+                finished: self.u8()? != 0,
+            });
+        }
+        Ok(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {DeltaEncoder, Tetris};
+
+    #[test]
+    fn events_round_trip() {
+        let events = vec![
+            AppEvent::InitWorker(2, 64),
+            AppEvent::InitTetris(1, 3, Preset::Classic, Goal::Endless),
+            AppEvent::InitTetris(0, 4, Preset::Tgm, Goal::Lines(40)),
+            AppEvent::Tick(0, 70_000),
+            AppEvent::User(1, 2, Some(vec![BlockEvent::Left, BlockEvent::Undo])),
+            AppEvent::User(1, 2, None),
+            AppEvent::User(0, 0, Some(vec![BlockEvent::Down; 300])),
+            AppEvent::Resync(3, 4),
+            AppEvent::TickAll(2),
+            AppEvent::UserAll(0, Some(vec![BlockEvent::Drop, BlockEvent::Hold])),
+            AppEvent::UserEach(1, vec![(0, vec![BlockEvent::Left]), (3, vec![BlockEvent::Rotate, BlockEvent::Drop])]),
+            AppEvent::Error(1, WorkerError::NoSuchBoard, Some(Box::new(AppEvent::Tick(1, 9)))),
+            AppEvent::Error(0, WorkerError::Decode, None),
+        ];
+
+        for codec in &[Codec::Json, Codec::Binary] {
+            for event in &events {
+                let data = codec.encode_event(event);
+                assert_eq!(Codec::of(&data), *codec);
+                let decoded = decode_event(&data).unwrap();
+                assert_eq!(format!("{:?}", decoded), format!("{:?}", event));
+            }
+        }
+    }
+
+    #[test]
+    fn msgs_round_trip() {
+        let mut tetris = Tetris::with_seed(21);
+        tetris.init();
+        for _ in 0..3 {
+            tetris.event(Some(vec![BlockEvent::Left, BlockEvent::Drop]));
+        }
+
+        let mut encoder = DeltaEncoder::new();
+        let full = encoder.message(AppEvent::InitTetris(0, 1, Preset::Classic, Goal::Lines(40)), &tetris);
+        tetris.event(Some(vec![BlockEvent::Rotate, BlockEvent::Drop]));
+        let delta = encoder.message(AppEvent::User(0, 1, None), &tetris);
+
+        let mut clearing = encoder.message(AppEvent::Tick(0, 1), &tetris);
+        clearing.clearing = Some(LineClear {
+            rows: vec![18, 19],
+            frames: 2,
+            delay: 20,
+        });
+        clearing.hold = Some(BlockType::S);
+        clearing.mode = Some(ModeState {
+            goal: Goal::Lines(40),
+            faults: 3,
+            finished: true,
+        });
+
+        for msg in &[full, delta, clearing] {
+            let json = decode_msg(&Codec::Json.encode_msg(msg)).unwrap();
+            let binary = Codec::Binary.encode_msg(msg);
+            let decoded = decode_msg(&binary).unwrap();
+            assert_eq!(format!("{:?}", decoded), format!("{:?}", json));
+            assert!(binary.len() < Codec::Json.encode_msg(msg).len());
+        }
+    }
+
+    #[test]
+    fn nesting_is_refused() {
+        let inner = AppEvent::Error(1, WorkerError::Decode, None);
+        let error = AppEvent::Error(0, WorkerError::Decode, Some(Box::new(inner)));
+        assert!(matches!(decode_event(&Codec::Binary.encode_event(&error)), Err(WireError::Nested)));
+
+        let mut tetris = Tetris::with_seed(4);
+        tetris.init();
+        let mut msg = DeltaEncoder::new().message(AppEvent::Tick(0, 0), &tetris);
+        if let Some(ref mut block) = msg.block {
+            let after = block.clone();
+            block.next.as_mut().unwrap().next = Some(Box::new(after));
+        }
+        assert!(matches!(decode_msg(&Codec::Binary.encode_msg(&msg)), Err(WireError::Nested)));
+    }
+
+    #[test]
+    fn msg_lists_round_trip() {
+        let mut tetris = Tetris::with_seed(4);
+        tetris.init();
+
+        let mut encoder = DeltaEncoder::new();
+        let msgs = vec![
+            encoder.message(AppEvent::Tick(1, 0), &tetris),
+            encoder.message(AppEvent::Tick(1, 0), &tetris),
+        ];
+
+        for codec in &[Codec::Json, Codec::Binary] {
+            let decoded = decode_msgs(&codec.encode_msgs(&msgs)).unwrap();
+            assert_eq!(decoded.len(), 2);
+            assert!(decoded[0].grid.is_some());
+            assert_eq!(decoded[1].seq, 2);
+        }
+        assert!(decode_msgs(&Codec::Binary.encode_msgs(&[])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn bad_input() {
+        match decode_msg(&[WIRE_VERSION, EVENT_TICK, 0]) {
+            Err(WireError::Truncated) => (),
+            other => panic!("unexpected: {:?}", other),
+        }
+        match decode_event(&[WIRE_VERSION, 42, 0, 0, 0, 0, 0]) {
+            Err(WireError::Tag(42)) => (),
+            other => panic!("unexpected: {:?}", other),
+        }
+        assert!(decode_event(b"{\"Nope\":1}").is_err());
+    }
+}
@@ -0,0 +1,94 @@
+/*
+ * C interface to tetris_core.
+ *
+ * Link against the tetris_ffi shared library. Every call that can fail
+ * returns one of the TETRIS_* status codes; the engine never aborts the
+ * host process.
+ *
+ * A board is created with tetris_new() and must be released with
+ * tetris_free(). Boards are not thread safe: use one board per thread or
+ * lock around every call.
+ *
+ * Safety: every board pointer passed in must be NULL or a live board from
+ * tetris_new() or tetris_new_sized() that was not freed yet. Other
+ * pointers must be NULL or valid for the len given with them, or for one
+ * value where no len is given. Nothing keeps a pointer after the call
+ * returns.
+ */
+#ifndef TETRIS_H
+#define TETRIS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct tetris tetris_t;
+
+/* Status codes. */
+#define TETRIS_OK 0
+#define TETRIS_NONE 1                 /* nothing to read, see below */
+#define TETRIS_ERR_NULL (-1)          /* a required pointer was NULL */
+#define TETRIS_ERR_BUFFER (-2)        /* the caller buffer is too small */
+#define TETRIS_ERR_EVENT (-3)         /* unknown event code */
+#define TETRIS_ERR_PANIC (-4)         /* the engine failed internally */
+
+/* Event codes accepted by tetris_event(). */
+#define TETRIS_EVENT_LEFT 0
+#define TETRIS_EVENT_RIGHT 1
+#define TETRIS_EVENT_DOWN 2
+#define TETRIS_EVENT_DROP 3
+#define TETRIS_EVENT_ROTATE 4
+#define TETRIS_EVENT_UNDO 5           /* take back the last placement */
+#define TETRIS_EVENT_REDO 6
+#define TETRIS_EVENT_HOLD 7           /* swap with the held block */
+
+/* Number of cells in a block. */
+#define TETRIS_BLOCK_CELLS 4
+
+/* Creates a board ready to play. Returns NULL on failure. */
+tetris_t *tetris_new(void);
+
+/*
+ * Creates a board of width 4..40 by height 4..60 cells. Returns NULL on
+ * failure or for a size out of range.
+ */
+tetris_t *tetris_new_sized(size_t width, size_t height);
+
+/* Releases a board. NULL is ignored. The board must not be used after. */
+void tetris_free(tetris_t *tetris);
+
+/* Advances the board by one frame. */
+int tetris_tick(tetris_t *tetris);
+
+/* Applies the events in order, as one frame of user input. */
+int tetris_event(tetris_t *tetris, const int32_t *events, size_t len);
+
+/* Writes the board width and height in cells. */
+int tetris_grid_size(const tetris_t *tetris, size_t *width, size_t *height);
+
+/*
+ * Copies the board cells row by row into buf, which must hold at least
+ * width * height bytes. 0 is an empty cell, 1..7 are the block types
+ * T, J, L, S, Z, O, I.
+ */
+int tetris_read_grid(const tetris_t *tetris, uint8_t *buf, size_t len);
+
+/*
+ * Writes the falling block type (1..7) and its cells as x, y pairs. xy must
+ * hold at least 2 * TETRIS_BLOCK_CELLS values. y is negative while the block
+ * is still above the board. Returns TETRIS_NONE between a lock and the
+ * next spawn, when nothing is falling.
+ */
+int tetris_read_block(const tetris_t *tetris, uint8_t *block_type, int32_t *xy, size_t len);
+
+/* Writes the type (1..7) of the next block, or returns TETRIS_NONE. */
+int tetris_read_next(const tetris_t *tetris, uint8_t *block_type);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* TETRIS_H */
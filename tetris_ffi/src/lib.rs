@@ -0,0 +1,335 @@
+//
+// C ABI for tetris_core, see include/tetris.h.
+//
+extern crate tetris_core as tc;
+
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+pub const TETRIS_OK: i32 = 0;
+// Nothing to read: no falling block during a delay, or no next block.
+pub const TETRIS_NONE: i32 = 1;
+pub const TETRIS_ERR_NULL: i32 = -1;
+pub const TETRIS_ERR_BUFFER: i32 = -2;
+pub const TETRIS_ERR_EVENT: i32 = -3;
+pub const TETRIS_ERR_PANIC: i32 = -4;
+
+pub const TETRIS_BLOCK_CELLS: usize = 4;
+
+fn to_block_event(code: i32) -> Option<tc::BlockEvent> {
+    match code {
+        0 => Some(tc::BlockEvent::Left),
+        1 => Some(tc::BlockEvent::Right),
+        2 => Some(tc::BlockEvent::Down),
+        3 => Some(tc::BlockEvent::Drop),
+        4 => Some(tc::BlockEvent::Rotate),
+        5 => Some(tc::BlockEvent::Undo),
+        6 => Some(tc::BlockEvent::Redo),
+        7 => Some(tc::BlockEvent::Hold),
+        _ => None,
+    }
+}
+
+//
+// Runs `f` and turns a panic into TETRIS_ERR_PANIC so it never unwinds
+// across the C boundary.
+//
+fn guard<F>(f: F) -> i32
+where
+    F: FnOnce() -> i32,
+{
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(code) => code,
+        Err(_) => TETRIS_ERR_PANIC,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn tetris_new() -> *mut tc::Tetris {
+    let tetris = panic::catch_unwind(|| {
+        let mut tetris = tc::Tetris::new();
+        tetris.init();
+        tetris
+    });
+
+    match tetris {
+        Ok(tetris) => Box::into_raw(Box::new(tetris)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+// NULL as well for a size out of range.
+#[no_mangle]
+pub extern "C" fn tetris_new_sized(width: usize, height: usize) -> *mut tc::Tetris {
+    let tetris = panic::catch_unwind(|| {
+        let grid = tc::Grid::with_size(width, height, tc::BUFFER_ROWS).ok()?;
+        let mut tetris = tc::Tetris::new();
+        tetris.grid = grid;
+        tetris.init();
+        Some(tetris)
+    });
+
+    match tetris {
+        Ok(Some(tetris)) => Box::into_raw(Box::new(tetris)),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// `tetris` is NULL or a board from tetris_new() or tetris_new_sized() that
+/// was not freed yet. It must not be used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn tetris_free(tetris: *mut tc::Tetris) {
+    if !tetris.is_null() {
+        drop(Box::from_raw(tetris));
+    }
+}
+
+/// # Safety
+///
+/// `tetris` is NULL or a live board from tetris_new() or tetris_new_sized(),
+/// not used by another thread during the call.
+#[no_mangle]
+pub unsafe extern "C" fn tetris_tick(tetris: *mut tc::Tetris) -> i32 {
+    let tetris = match tetris.as_mut() {
+        Some(tetris) => tetris,
+        None => return TETRIS_ERR_NULL,
+    };
+
+    guard(|| {
+        tetris.tick();
+        TETRIS_OK
+    })
+}
+
+/// # Safety
+///
+/// `tetris` is NULL or a live board from tetris_new() or tetris_new_sized(),
+/// not used by another thread during the call.
+/// `events` is NULL with `len` 0, or points to `len` readable values.
+#[no_mangle]
+pub unsafe extern "C" fn tetris_event(tetris: *mut tc::Tetris, events: *const i32, len: usize) -> i32 {
+    let tetris = match tetris.as_mut() {
+        Some(tetris) => tetris,
+        None => return TETRIS_ERR_NULL,
+    };
+
+    if events.is_null() && len > 0 {
+        return TETRIS_ERR_NULL;
+    }
+
+    let codes = if len > 0 {
+        slice::from_raw_parts(events, len)
+    } else {
+        &[]
+    };
+
+    let mut block_events = Vec::with_capacity(len);
+    for code in codes {
+        match to_block_event(*code) {
+            Some(event) => block_events.push(event),
+            None => return TETRIS_ERR_EVENT,
+        }
+    }
+
+    guard(|| {
+        tetris.event(Some(block_events));
+        TETRIS_OK
+    })
+}
+
+/// # Safety
+///
+/// `tetris` is NULL or a live board from tetris_new() or tetris_new_sized(),
+/// not used by another thread during the call.
+/// `width` and `height` are NULL or point to writable values.
+#[no_mangle]
+pub unsafe extern "C" fn tetris_grid_size(
+    tetris: *const tc::Tetris,
+    width: *mut usize,
+    height: *mut usize,
+) -> i32 {
+    let tetris = match tetris.as_ref() {
+        Some(tetris) => tetris,
+        None => return TETRIS_ERR_NULL,
+    };
+
+    if width.is_null() || height.is_null() {
+        return TETRIS_ERR_NULL;
+    }
+
+    *width = tetris.grid.width();
+    *height = tetris.grid.height();
+    TETRIS_OK
+}
+
+/// # Safety
+///
+/// `tetris` is NULL or a live board from tetris_new() or tetris_new_sized(),
+/// not used by another thread during the call.
+/// `buf` is NULL or points to `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn tetris_read_grid(tetris: *const tc::Tetris, buf: *mut u8, len: usize) -> i32 {
+    let tetris = match tetris.as_ref() {
+        Some(tetris) => tetris,
+        None => return TETRIS_ERR_NULL,
+    };
+
+    if buf.is_null() {
+        return TETRIS_ERR_NULL;
+    }
+
+    let cells = tetris.grid.as_slice();
+    if len < cells.len() {
+        return TETRIS_ERR_BUFFER;
+    }
+
+    ptr::copy_nonoverlapping(cells.as_ptr(), buf, cells.len());
+    TETRIS_OK
+}
+
+/// TETRIS_NONE between a lock and the next spawn, when nothing falls.
+///
+/// # Safety
+///
+/// `tetris` is NULL or a live board from tetris_new() or tetris_new_sized(),
+/// not used by another thread during the call.
+/// `block_type` is NULL or writable, `xy` is NULL or points to `len`
+/// writable values.
+#[no_mangle]
+pub unsafe extern "C" fn tetris_read_block(
+    tetris: *const tc::Tetris,
+    block_type: *mut u8,
+    xy: *mut i32,
+    len: usize,
+) -> i32 {
+    let tetris = match tetris.as_ref() {
+        Some(tetris) => tetris,
+        None => return TETRIS_ERR_NULL,
+    };
+
+    if block_type.is_null() || xy.is_null() {
+        return TETRIS_ERR_NULL;
+    }
+
+    let block = tetris.get_block();
+    let points = block.points_ref();
+    if points.is_empty() {
+        return TETRIS_NONE;
+    }
+    if len < points.len() * 2 {
+        return TETRIS_ERR_BUFFER;
+    }
+
+    let xy = slice::from_raw_parts_mut(xy, points.len() * 2);
+    for (i, point) in points.iter().enumerate() {
+        xy[i * 2] = point.x();
+        xy[i * 2 + 1] = point.y();
+    }
+    *block_type = block.type_ref().index();
+    TETRIS_OK
+}
+
+/// TETRIS_NONE when there is no next block.
+///
+/// # Safety
+///
+/// `tetris` is NULL or a live board from tetris_new() or tetris_new_sized(),
+/// not used by another thread during the call.
+/// `block_type` is NULL or writable.
+#[no_mangle]
+pub unsafe extern "C" fn tetris_read_next(tetris: *const tc::Tetris, block_type: *mut u8) -> i32 {
+    let tetris = match tetris.as_ref() {
+        Some(tetris) => tetris,
+        None => return TETRIS_ERR_NULL,
+    };
+
+    if block_type.is_null() {
+        return TETRIS_ERR_NULL;
+    }
+
+    match tetris.block.next_type() {
+        Some(next) => {
+            *block_type = next.index();
+            TETRIS_OK
+        }
+        None => TETRIS_NONE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn play_and_read() {
+        unsafe {
+            let tetris = tetris_new();
+            assert!(!tetris.is_null());
+
+            let mut width = 0;
+            let mut height = 0;
+            assert_eq!(tetris_grid_size(tetris, &mut width, &mut height), TETRIS_OK);
+            assert_eq!((width, height), (tc::COLUMNS, tc::ROWS));
+
+            let events = [0, 3];
+            assert_eq!(tetris_event(tetris, events.as_ptr(), events.len()), TETRIS_OK);
+            assert_eq!(tetris_tick(tetris), TETRIS_OK);
+
+            let mut cells = vec![0_u8; width * height];
+            assert_eq!(tetris_read_grid(tetris, cells.as_mut_ptr(), cells.len()), TETRIS_OK);
+            assert_eq!(cells.iter().filter(|c| **c > 0).count(), TETRIS_BLOCK_CELLS);
+
+            let mut block_type = 0;
+            let mut xy = [0_i32; TETRIS_BLOCK_CELLS * 2];
+            assert_eq!(
+                tetris_read_block(tetris, &mut block_type, xy.as_mut_ptr(), xy.len()),
+                TETRIS_OK
+            );
+            assert!((1..=7).contains(&block_type));
+
+            tetris_free(tetris);
+
+            let tetris = tetris_new_sized(4, 40);
+            assert_eq!(tetris_grid_size(tetris, &mut width, &mut height), TETRIS_OK);
+            assert_eq!((width, height), (4, 40));
+            tetris_free(tetris);
+            assert!(tetris_new_sized(41, 20).is_null());
+        }
+    }
+
+    #[test]
+    fn error_codes() {
+        unsafe {
+            assert_eq!(tetris_tick(ptr::null_mut()), TETRIS_ERR_NULL);
+
+            let tetris = tetris_new();
+            let events = [42];
+            assert_eq!(tetris_event(tetris, events.as_ptr(), events.len()), TETRIS_ERR_EVENT);
+
+            let mut cells = [0_u8; 4];
+            assert_eq!(tetris_read_grid(tetris, cells.as_mut_ptr(), cells.len()), TETRIS_ERR_BUFFER);
+            tetris_free(tetris);
+
+            // Nothing falls during the entry delay.
+            let tetris = Box::into_raw(Box::new({
+                let mut tetris = tc::Tetris::new();
+                tetris.ruleset.spawn_delay = 10;
+                tetris.init();
+                tetris
+            }));
+            let events = [3];
+            assert_eq!(tetris_event(tetris, events.as_ptr(), events.len()), TETRIS_OK);
+            let mut block_type = 0;
+            let mut xy = [0_i32; TETRIS_BLOCK_CELLS * 2];
+            assert_eq!(
+                tetris_read_block(tetris, &mut block_type, xy.as_mut_ptr(), xy.len()),
+                TETRIS_NONE
+            );
+            tetris_free(tetris);
+            tetris_free(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,246 @@
+extern crate numpy;
+extern crate pyo3;
+extern crate tetris_core as tc;
+
+use numpy::PyArray2;
+use numpy::ndarray::ArrayView2;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+fn sized_grid(width: usize, height: usize) -> PyResult<tc::Grid> {
+    tc::Grid::with_size(width, height, tc::BUFFER_ROWS).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+//
+// Wraps the board cells in a read-only numpy array without copying them.
+// The grid buffer never moves, so the array reads the live board for as long
+// as `owner` is alive. Undo, redo and restore copy into the buffer instead of
+// reallocating it, and must keep doing so.
+//
+fn board_view<'py>(grid: &tc::Grid, owner: Bound<'py, PyAny>) -> PyResult<Bound<'py, PyArray2<u8>>> {
+    let view = ArrayView2::from_shape((grid.height(), grid.width()), grid.as_slice())
+        .expect("Grid size mismatch!");
+    let array = unsafe { PyArray2::borrow_from_array(&view, owner) };
+    array.call_method1("setflags", (false,))?;
+    Ok(array)
+}
+
+#[pyclass(name = "BlockEvent", eq, eq_int)]
+#[derive(Clone, PartialEq)]
+pub enum PyBlockEvent {
+    Left,
+    Right,
+    Down,
+    Drop,
+    Rotate,
+    Undo,
+    Redo,
+    Hold,
+}
+
+impl PyBlockEvent {
+    fn to_core(&self) -> tc::BlockEvent {
+        match *self {
+            PyBlockEvent::Left => tc::BlockEvent::Left,
+            PyBlockEvent::Right => tc::BlockEvent::Right,
+            PyBlockEvent::Down => tc::BlockEvent::Down,
+            PyBlockEvent::Drop => tc::BlockEvent::Drop,
+            PyBlockEvent::Rotate => tc::BlockEvent::Rotate,
+            PyBlockEvent::Undo => tc::BlockEvent::Undo,
+            PyBlockEvent::Redo => tc::BlockEvent::Redo,
+            PyBlockEvent::Hold => tc::BlockEvent::Hold,
+        }
+    }
+}
+
+#[pyclass(name = "BlockType", eq, eq_int)]
+#[derive(Clone, PartialEq)]
+pub enum PyBlockType {
+    T,
+    J,
+    L,
+    S,
+    Z,
+    O,
+    I,
+}
+
+impl PyBlockType {
+    fn from_core(block_type: &tc::BlockType) -> PyBlockType {
+        match *block_type {
+            tc::BlockType::T => PyBlockType::T,
+            tc::BlockType::J => PyBlockType::J,
+            tc::BlockType::L => PyBlockType::L,
+            tc::BlockType::S => PyBlockType::S,
+            tc::BlockType::Z => PyBlockType::Z,
+            tc::BlockType::O => PyBlockType::O,
+            tc::BlockType::I => PyBlockType::I,
+        }
+    }
+
+    fn to_core(&self) -> tc::BlockType {
+        match *self {
+            PyBlockType::T => tc::BlockType::T,
+            PyBlockType::J => tc::BlockType::J,
+            PyBlockType::L => tc::BlockType::L,
+            PyBlockType::S => tc::BlockType::S,
+            PyBlockType::Z => tc::BlockType::Z,
+            PyBlockType::O => tc::BlockType::O,
+            PyBlockType::I => tc::BlockType::I,
+        }
+    }
+}
+
+#[pymethods]
+impl PyBlockType {
+    fn index(&self) -> u8 {
+        self.to_core().index()
+    }
+}
+
+#[pyclass(name = "Block")]
+#[derive(Clone)]
+pub struct PyBlock {
+    inner: tc::Block,
+}
+
+#[pymethods]
+impl PyBlock {
+    #[getter]
+    fn block_type(&self) -> PyBlockType {
+        PyBlockType::from_core(self.inner.type_ref())
+    }
+
+    #[getter]
+    fn color(&self) -> (u8, u8, u8) {
+        *self.inner.color_ref()
+    }
+
+    #[getter]
+    fn points(&self) -> Vec<(i32, i32)> {
+        self.inner
+            .points_ref()
+            .iter()
+            .map(|point| (point.x(), point.y()))
+            .collect()
+    }
+
+    #[getter]
+    fn next(&self) -> Option<PyBlock> {
+        self.inner.next_ref().as_ref().map(|next| {
+            PyBlock { inner: (**next).clone() }
+        })
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Block({:?}, {:?})", self.inner.type_ref(), self.points())
+    }
+}
+
+#[pyclass(name = "Grid")]
+pub struct PyGrid {
+    inner: tc::Grid,
+}
+
+#[pymethods]
+impl PyGrid {
+    #[new]
+    #[pyo3(signature = (width = tc::COLUMNS, height = tc::ROWS))]
+    fn new(width: usize, height: usize) -> PyResult<PyGrid> {
+        Ok(PyGrid { inner: sized_grid(width, height)? })
+    }
+
+    #[getter]
+    fn width(&self) -> usize {
+        self.inner.width()
+    }
+
+    #[getter]
+    fn height(&self) -> usize {
+        self.inner.height()
+    }
+
+    #[getter]
+    fn data<'py>(slf: Bound<'py, Self>) -> PyResult<Bound<'py, PyArray2<u8>>> {
+        let owner = slf.clone().into_any();
+        board_view(&slf.borrow().inner, owner)
+    }
+
+    fn fill(&mut self, block: &PyBlock) {
+        self.inner.fill(&block.inner);
+    }
+
+    fn is_empty(&self, block: &PyBlock) -> bool {
+        self.inner.is_empty(block.inner.points_ref())
+    }
+
+    fn is_empty_below(&self, block: &PyBlock) -> bool {
+        self.inner.is_empty_below(block.inner.points_ref())
+    }
+}
+
+#[pyclass(name = "Tetris")]
+pub struct PyTetris {
+    inner: tc::Tetris,
+}
+
+#[pymethods]
+impl PyTetris {
+    #[new]
+    #[pyo3(signature = (width = tc::COLUMNS, height = tc::ROWS))]
+    fn new(width: usize, height: usize) -> PyResult<PyTetris> {
+        let mut tetris = tc::Tetris::new();
+        tetris.grid = sized_grid(width, height)?;
+        tetris.init();
+        Ok(PyTetris { inner: tetris })
+    }
+
+    fn tick(&mut self) {
+        self.inner.tick();
+    }
+
+    fn event(&mut self, events: Vec<PyBlockEvent>) {
+        self.inner.event(Some(
+            events.iter().map(|event| event.to_core()).collect(),
+        ));
+    }
+
+    #[getter]
+    fn block(&self) -> PyBlock {
+        PyBlock { inner: self.inner.get_block() }
+    }
+
+    #[getter]
+    fn grid(&self) -> PyGrid {
+        PyGrid { inner: self.inner.get_grid() }
+    }
+
+    //
+    // Live view of the board: it follows every `tick()` and `event()`.
+    //
+    #[getter]
+    fn board<'py>(slf: Bound<'py, Self>) -> PyResult<Bound<'py, PyArray2<u8>>> {
+        let owner = slf.clone().into_any();
+        board_view(&slf.borrow().inner.grid, owner)
+    }
+
+    fn color(&self, block_type: &PyBlockType) -> (u8, u8, u8) {
+        self.inner.scheme.color(&block_type.to_core())
+    }
+}
+
+#[pymodule]
+fn tetris(m: &Bound<PyModule>) -> PyResult<()> {
+    m.add_class::<PyBlockEvent>()?;
+    m.add_class::<PyBlockType>()?;
+    m.add_class::<PyBlock>()?;
+    m.add_class::<PyGrid>()?;
+    m.add_class::<PyTetris>()?;
+    m.add("COLUMNS", tc::COLUMNS)?;
+    m.add("ROWS", tc::ROWS)?;
+    m.add("MIN_COLUMNS", tc::MIN_COLUMNS)?;
+    m.add("MAX_COLUMNS", tc::MAX_COLUMNS)?;
+    m.add("MIN_ROWS", tc::MIN_ROWS)?;
+    m.add("MAX_ROWS", tc::MAX_ROWS)?;
+    Ok(())
+}
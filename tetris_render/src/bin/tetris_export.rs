@@ -0,0 +1,49 @@
+//
+// Exports a recorded game without opening a window:
+//
+//     tetris_export <replay> <out.gif> [--scale <n>] [--skip <n>]
+//     tetris_export <replay> <out.png> [--scale <n>] [--frame <n>]
+//
+// A GIF gets the whole game, one image every `--skip` frames. A PNG gets
+// the board at `--frame`, or at the end of the game.
+//
+extern crate tetris_core as tc;
+extern crate tetris_render as tr;
+
+use std::env;
+use std::fs::File;
+use std::io::BufWriter;
+use std::process;
+
+fn number_arg(name: &str, default: u32) -> u32 {
+    match env::args().skip_while(|arg| arg != name).nth(1) {
+        Some(value) => value.parse().expect("Not a number!"),
+        None => default,
+    }
+}
+
+fn main() {
+    let paths: Vec<String> = env::args()
+        .skip(1)
+        .take_while(|arg| !arg.starts_with("--"))
+        .collect();
+    if paths.len() != 2 {
+        eprintln!("usage: tetris_export <replay> <out.gif|out.png> [--scale <n>] [--skip <n>] [--frame <n>]");
+        process::exit(2);
+    }
+
+    let mut file = File::open(&paths[0]).expect("Can not open the replay file!");
+    let replay = tc::Replay::read_from(&mut file).expect("Can not read the replay file!");
+    let out = BufWriter::new(File::create(&paths[1]).expect("Can not create the output file!"));
+    let scale = number_arg("--scale", 8);
+
+    if paths[1].ends_with(".png") {
+        let mut player = tc::ReplayPlayer::new(replay).expect("Can not play the replay file!");
+        let frame = number_arg("--frame", player.frames());
+        player.seek(frame);
+        let framebuffer = tr::render(&tr::state_message(player.tetris()), scale);
+        tr::write_png(&framebuffer, out).expect("Can not write the png!");
+    } else {
+        tr::replay_gif(replay, out, scale, number_arg("--skip", 2)).expect("Can not write the gif!");
+    }
+}
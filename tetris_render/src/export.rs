@@ -0,0 +1,337 @@
+use gif;
+use png;
+use tc;
+
+use std::error;
+use std::fmt;
+use std::io::{self, Write};
+
+use {board_size, message_size, paint_message, Framebuffer, Rect};
+
+//
+// One board message drawn `scale` pixels per cell, labels included.
+//
+pub fn render(message: &tc::Msg, scale: u32) -> Framebuffer {
+    let scale = scale.max(1);
+    let (width, height) = message_size(message);
+    let mut board = Framebuffer::new(width, height);
+    let mut target = Framebuffer::new(width * scale, height * scale);
+    let rect = Rect::new(0, 0, target.width(), target.height());
+    paint_message(
+        &mut target,
+        &mut board,
+        message,
+        message.event.worker_id(),
+        message.event.tetris_id(),
+        rect,
+    );
+    target
+}
+
+// The full state of a local game, as a worker would send it.
+pub fn state_message(tetris: &tc::Tetris) -> tc::Msg {
+    let mut message = tc::Msg::new(
+        tc::AppEvent::Tick(0, 0),
+        Some(tetris.get_block()),
+        Some(tetris.grid.visible()),
+        Some(tetris.scheme.clone()),
+    );
+    message.stats = Some(tetris.stats.clone());
+    message.game_over = tetris.is_game_over();
+    message.clearing = tetris.clearing().cloned();
+    message.hold = tetris.hold().cloned();
+    message
+}
+
+pub fn write_png<W: Write>(framebuffer: &Framebuffer, writer: W) -> Result<(), ExportError> {
+    let mut encoder = png::Encoder::new(writer, framebuffer.width(), framebuffer.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(framebuffer.as_bytes())?;
+    Ok(())
+}
+
+// GIF delays are in hundredths of a second.
+fn centiseconds(frame: u32) -> u64 {
+    frame as u64 * 100 / tc::FRAMES_PER_SECOND as u64
+}
+
+//
+// Encodes the messages of one board into a looping GIF. Every message is a
+// frame unless it comes less than `frame_skip` game frames after the last
+// one kept, and each frame is shown for as long as the game took to reach
+// the next, using the frame count in the stats. The first game over
+// message is always kept, the ones after it never.
+//
+// A frame is only written once the next one arrives, so call finish() to
+// write the last. Messages of another board than the last one start over
+// from that board's frame count. Every message must be of a board of the
+// same size.
+//
+pub struct GifRecorder<W: Write> {
+    encoder: gif::Encoder<W>,
+    width: u32,
+    height: u32,
+    scale: u32,
+    frame_skip: u32,
+    // The last frame kept, with its game frame.
+    pending: Option<(Framebuffer, u32)>,
+    // The board of the last message and whether its game over is kept.
+    board: Option<(u8, u32)>,
+    game_over: bool,
+    // Stands in for the game frame when messages have no stats.
+    messages: u32,
+}
+
+impl<W: Write> GifRecorder<W> {
+    pub fn new(writer: W, scale: u32, frame_skip: u32) -> Result<GifRecorder<W>, ExportError> {
+        GifRecorder::for_board(writer, (tc::COLUMNS, tc::ROWS), scale, frame_skip)
+    }
+
+    // For boards of `columns` by `rows` cells.
+    pub fn for_board(
+        writer: W,
+        (columns, rows): (usize, usize),
+        scale: u32,
+        frame_skip: u32,
+    ) -> Result<GifRecorder<W>, ExportError> {
+        let scale = scale.max(1);
+        let (width, height) = board_size(columns, rows);
+        let (width, height) = (width * scale, height * scale);
+        if width > u16::MAX as u32 || height > u16::MAX as u32 {
+            return Err(ExportError::Size);
+        }
+
+        let mut encoder = gif::Encoder::new(writer, width as u16, height as u16, &[])?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        Ok(GifRecorder {
+            encoder: encoder,
+            width: width,
+            height: height,
+            scale: scale,
+            frame_skip: frame_skip.max(1),
+            pending: None,
+            board: None,
+            game_over: false,
+            messages: 0,
+        })
+    }
+
+    pub fn frame(&mut self, message: &tc::Msg) -> Result<(), ExportError> {
+        let frame = match message.stats {
+            Some(ref stats) => stats.frames,
+            None => self.messages,
+        };
+        self.messages += 1;
+
+        let board = Some((message.event.worker_id(), message.event.tetris_id()));
+        if board != self.board {
+            self.board = board;
+            self.game_over = false;
+            self.flush()?;
+        }
+        if message.game_over && self.game_over {
+            return Ok(());
+        }
+        self.game_over = message.game_over;
+
+        if let Some((_, last)) = self.pending {
+            if frame < last + self.frame_skip && !message.game_over {
+                return Ok(());
+            }
+        }
+
+        let framebuffer = render(message, self.scale);
+        if (framebuffer.width(), framebuffer.height()) != (self.width, self.height) {
+            return Err(ExportError::Board);
+        }
+        if let Some((last_framebuffer, last)) = self.pending.take() {
+            self.write(last_framebuffer, centiseconds(frame).saturating_sub(centiseconds(last)))?;
+        }
+        self.pending = Some((framebuffer, frame));
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W, ExportError> {
+        self.flush()?;
+        Ok(self.encoder.into_inner()?)
+    }
+
+    // Writes the last frame kept, shown for `frame_skip` game frames.
+    fn flush(&mut self) -> Result<(), ExportError> {
+        if let Some((framebuffer, frame)) = self.pending.take() {
+            let delay = centiseconds(frame + self.frame_skip) - centiseconds(frame);
+            self.write(framebuffer, delay)?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, framebuffer: Framebuffer, delay: u64) -> Result<(), ExportError> {
+        let width = framebuffer.width() as u16;
+        let height = framebuffer.height() as u16;
+        let mut pixels = framebuffer.as_bytes().to_vec();
+        let mut frame = gif::Frame::from_rgba_speed(width, height, &mut pixels, 10);
+        // Browsers show delays under 2 as 10, so 2 is the fastest.
+        frame.delay = delay.max(2).min(u16::MAX as u64) as u16;
+        self.encoder.write_frame(&frame)?;
+        Ok(())
+    }
+}
+
+//
+// Plays `replay` from the start and records every board state it goes
+// through.
+//
+pub fn replay_gif<W: Write>(
+    replay: tc::Replay,
+    writer: W,
+    scale: u32,
+    frame_skip: u32,
+) -> Result<W, ExportError> {
+    let board = (replay.header.columns, replay.header.rows);
+    let mut player = tc::ReplayPlayer::new(replay)?;
+    let mut recorder = GifRecorder::for_board(writer, board, scale, frame_skip)?;
+    recorder.frame(&state_message(player.tetris()))?;
+    while player.step() {
+        recorder.frame(&state_message(player.tetris()))?;
+    }
+    recorder.finish()
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Io(io::Error),
+    Png(png::EncodingError),
+    Gif(gif::EncodingError),
+    // GIF frames are at most 65535 pixels wide and high.
+    Size,
+    // A message of a board of another size than the GIF's.
+    Board,
+    Replay(tc::ReplayError),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExportError::Io(ref e) => write!(f, "export io error: {}", e),
+            ExportError::Png(ref e) => write!(f, "png error: {}", e),
+            ExportError::Gif(ref e) => write!(f, "gif error: {}", e),
+            ExportError::Size => write!(f, "image too large, lower the scale"),
+            ExportError::Board => write!(f, "board size differs from the gif"),
+            ExportError::Replay(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for ExportError {}
+
+impl From<io::Error> for ExportError {
+    fn from(e: io::Error) -> ExportError {
+        ExportError::Io(e)
+    }
+}
+
+impl From<png::EncodingError> for ExportError {
+    fn from(e: png::EncodingError) -> ExportError {
+        ExportError::Png(e)
+    }
+}
+
+impl From<tc::ReplayError> for ExportError {
+    fn from(e: tc::ReplayError) -> ExportError {
+        ExportError::Replay(e)
+    }
+}
+
+impl From<gif::EncodingError> for ExportError {
+    fn from(e: gif::EncodingError) -> ExportError {
+        ExportError::Gif(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replay() -> tc::Replay {
+        let mut tetris = tc::Tetris::with_seed(3);
+        tetris.init();
+        tetris.start_recording();
+        for frame in 0..300 {
+            if frame % 40 == 0 {
+                tetris.event(Some(vec![tc::BlockEvent::Drop]));
+            }
+            tetris.tick();
+        }
+        tetris.take_replay().unwrap()
+    }
+
+    #[test]
+    fn png_header() {
+        let mut tetris = tc::Tetris::with_seed(3);
+        tetris.init();
+
+        let mut data = Vec::new();
+        write_png(&render(&state_message(&tetris), 2), &mut data).unwrap();
+        assert_eq!(&data[..8], b"\x89PNG\r\n\x1a\n");
+        // IHDR width and height.
+        assert_eq!(&data[16..24], &[0, 0, 0, 44, 0, 0, 0, 44]);
+    }
+
+    #[test]
+    fn gif_frames() {
+        let data = replay_gif(replay(), Vec::new(), 1, 30).unwrap();
+        assert_eq!(&data[..6], b"GIF89a");
+
+        // Graphic control extensions, one per frame: 300 frames every 30
+        // plus the first.
+        let frames = data.windows(3).filter(|w| w == &[0x21, 0xF9, 0x04]).count();
+        assert_eq!(frames, 11);
+    }
+
+    #[test]
+    fn game_over_and_board_changes() {
+        let count = |data: &[u8]| data.windows(3).filter(|w| w == &[0x21, 0xF9, 0x04]).count();
+        let mut tetris = tc::Tetris::with_seed(3);
+        tetris.init();
+        let mut message = state_message(&tetris);
+
+        // A dead board adds one frame, however many messages it sends.
+        message.game_over = true;
+        let mut recorder = GifRecorder::new(Vec::new(), 1, 30).unwrap();
+        for frame in 0..120 {
+            message.stats.as_mut().unwrap().frames = frame;
+            recorder.frame(&message).unwrap();
+        }
+        assert_eq!(count(&recorder.finish().unwrap()), 1);
+
+        // Another board further behind starts over instead of going back.
+        message.game_over = false;
+        let mut recorder = GifRecorder::new(Vec::new(), 1, 30).unwrap();
+        message.stats.as_mut().unwrap().frames = 500;
+        recorder.frame(&message).unwrap();
+        message.event = tc::AppEvent::Tick(0, 1);
+        message.stats.as_mut().unwrap().frames = 10;
+        recorder.frame(&message).unwrap();
+        message.stats.as_mut().unwrap().frames = 45;
+        recorder.frame(&message).unwrap();
+        assert_eq!(count(&recorder.finish().unwrap()), 3);
+    }
+
+    #[test]
+    fn narrow_board() {
+        let mut tetris = tc::Tetris::with_seed(3);
+        tetris.grid = tc::Grid::with_size(4, 30, 0).unwrap();
+        tetris.init();
+        let message = state_message(&tetris);
+
+        let framebuffer = render(&message, 1);
+        assert_eq!((framebuffer.width(), framebuffer.height()), (16, 32));
+
+        let mut recorder = GifRecorder::new(Vec::new(), 1, 1).unwrap();
+        assert!(recorder.frame(&message).is_err());
+        let mut recorder = GifRecorder::for_board(Vec::new(), (4, 30), 1, 1).unwrap();
+        assert!(recorder.frame(&message).is_ok());
+    }
+}
@@ -0,0 +1,209 @@
+use std::error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use {Rect, Renderer, Rgb};
+
+//
+// RGBA pixels in memory, row by row. Needs no display, so frames can be
+// rendered and compared in tests.
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Framebuffer {
+        Framebuffer {
+            width: width,
+            height: height,
+            pixels: vec![0; (width * height * 4) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Option<Rgb> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let i = ((y * self.width + x) * 4) as usize;
+        Some((self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]))
+    }
+
+    fn set(&mut self, x: i32, y: i32, (r, g, b): Rgb) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        let i = ((y as u32 * self.width + x as u32) * 4) as usize;
+        self.pixels[i..i + 4].copy_from_slice(&[r, g, b, 255]);
+    }
+
+    //
+    // Binary PPM (P6). Alpha is dropped, every pixel drawn here is opaque.
+    //
+    pub fn write_ppm<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+        let rgb: Vec<u8> = self.pixels
+            .chunks(4)
+            .flat_map(|pixel| pixel[0..3].iter().cloned())
+            .collect();
+        writer.write_all(&rgb)
+    }
+
+    pub fn read_ppm<R: Read>(reader: &mut R) -> Result<Framebuffer, PpmError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        // Magic, width, height and max value, each followed by one blank.
+        let mut fields = Vec::new();
+        let mut pos = 0;
+        while fields.len() < 4 {
+            let start = pos;
+            while pos < data.len() && !(data[pos] as char).is_whitespace() {
+                pos += 1;
+            }
+            if pos >= data.len() {
+                return Err(PpmError::Header);
+            }
+            fields.push(String::from_utf8_lossy(&data[start..pos]).into_owned());
+            pos += 1;
+        }
+
+        if fields[0] != "P6" || fields[3] != "255" {
+            return Err(PpmError::Header);
+        }
+        let width: u32 = fields[1].parse().map_err(|_| PpmError::Header)?;
+        let height: u32 = fields[2].parse().map_err(|_| PpmError::Header)?;
+
+        let rgb = &data[pos..];
+        if rgb.len() != (width * height * 3) as usize {
+            return Err(PpmError::Size);
+        }
+
+        let mut framebuffer = Framebuffer::new(width, height);
+        for (pixel, source) in framebuffer.pixels.chunks_mut(4).zip(rgb.chunks(3)) {
+            pixel[0..3].copy_from_slice(source);
+            pixel[3] = 255;
+        }
+        Ok(framebuffer)
+    }
+}
+
+impl Renderer for Framebuffer {
+    fn fill_rect(&mut self, rect: Rect, color: Rgb) {
+        for y in rect.y..rect.bottom() {
+            for x in rect.x..rect.right() {
+                self.set(x, y, color);
+            }
+        }
+    }
+
+    fn draw_points(&mut self, points: &[(i32, i32)], color: Rgb) {
+        for &(x, y) in points {
+            self.set(x, y, color);
+        }
+    }
+
+    // Nearest neighbour, which keeps the cells sharp.
+    fn blit(&mut self, source: &Framebuffer, dest: Rect) {
+        if source.width == 0 || source.height == 0 {
+            return;
+        }
+
+        for dy in 0..dest.height {
+            let sy = dy * source.height / dest.height;
+            for dx in 0..dest.width {
+                let sx = dx * source.width / dest.width;
+                if let Some(color) = source.pixel(sx, sy) {
+                    self.set(dest.x + dx as i32, dest.y + dy as i32, color);
+                }
+            }
+        }
+    }
+
+    fn clear(&mut self, (r, g, b): Rgb) {
+        for pixel in self.pixels.chunks_mut(4) {
+            pixel.copy_from_slice(&[r, g, b, 255]);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PpmError {
+    Io(io::Error),
+    Header,
+    Size,
+}
+
+impl fmt::Display for PpmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PpmError::Io(ref e) => write!(f, "ppm io error: {}", e),
+            PpmError::Header => write!(f, "not a binary ppm file"),
+            PpmError::Size => write!(f, "ppm pixel data does not match its size"),
+        }
+    }
+}
+
+impl error::Error for PpmError {}
+
+impl From<io::Error> for PpmError {
+    fn from(e: io::Error) -> PpmError {
+        PpmError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draw_and_blit() {
+        let mut small = Framebuffer::new(2, 2);
+        small.clear((1, 2, 3));
+        small.draw_points(&[(1, 1), (5, 5)], (9, 9, 9));
+
+        let mut big = Framebuffer::new(8, 8);
+        big.blit(&small, Rect::new(2, 2, 4, 4));
+        assert_eq!(big.pixel(0, 0), Some((0, 0, 0)));
+        assert_eq!(big.pixel(2, 2), Some((1, 2, 3)));
+        assert_eq!(big.pixel(5, 5), Some((9, 9, 9)));
+        assert_eq!(big.pixel(6, 6), Some((0, 0, 0)));
+
+        big.draw_rect(Rect::new(-2, 0, 4, 3), (7, 7, 7));
+        assert_eq!(big.pixel(1, 1), Some((7, 7, 7)));
+        assert_eq!(big.pixel(0, 1), Some((0, 0, 0)));
+    }
+
+    #[test]
+    fn ppm_round_trip() {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        framebuffer.fill_rect(Rect::new(1, 0, 2, 1), (200, 100, 50));
+
+        let mut data = Vec::new();
+        framebuffer.write_ppm(&mut data).unwrap();
+        assert_eq!(Framebuffer::read_ppm(&mut data.as_slice()).unwrap(), {
+            let mut opaque = framebuffer.clone();
+            opaque.fill_rect(Rect::new(0, 1, 1, 1), (0, 0, 0));
+            opaque.fill_rect(Rect::new(0, 0, 1, 1), (0, 0, 0));
+            opaque.fill_rect(Rect::new(1, 1, 2, 1), (0, 0, 0));
+            opaque
+        });
+
+        assert!(Framebuffer::read_ppm(&mut &b"P3\n1 1\n255\n"[..]).is_err());
+    }
+}
@@ -0,0 +1,449 @@
+//
+// Drawing of boards, labels and overlays behind a small Renderer trait, so
+// the same code paints the SDL window and an in-memory framebuffer.
+//
+extern crate gif;
+extern crate png;
+extern crate tetris_core as tc;
+
+mod export;
+mod font;
+mod framebuffer;
+
+pub use export::{render, replay_gif, state_message, write_png, ExportError, GifRecorder};
+pub use font::{text_rects, text_width, GLYPH_HEIGHT, GLYPH_WIDTH};
+pub use framebuffer::{Framebuffer, PpmError};
+
+pub const BORDER: u32 = 1;
+pub const RIGHT_PANEL: u32 = 9;
+// Rows the right panel needs for the next and held blocks.
+pub const PANEL_ROWS: u32 = 8;
+// The size of a default board, see board_size().
+pub const MAIN_WIDTH: u32 = BORDER + tc::COLUMNS as u32 + BORDER;
+pub const BOARD_WIDTH: u32 = MAIN_WIDTH + RIGHT_PANEL + BORDER;
+pub const BOARD_HEIGHT: u32 = BORDER + tc::ROWS as u32 + BORDER;
+
+pub type Rgb = (u8, u8, u8);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Rect {
+        Rect {
+            x: x,
+            y: y,
+            width: width,
+            height: height,
+        }
+    }
+
+    pub fn right(&self) -> i32 {
+        self.x + self.width as i32
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height as i32
+    }
+}
+
+pub trait Renderer {
+    fn fill_rect(&mut self, rect: Rect, color: Rgb);
+
+    fn draw_points(&mut self, points: &[(i32, i32)], color: Rgb);
+
+    // Scales all of `source` into `dest`.
+    fn blit(&mut self, source: &Framebuffer, dest: Rect);
+
+    fn clear(&mut self, color: Rgb);
+
+    // One pixel wide outline.
+    fn draw_rect(&mut self, rect: Rect, color: Rgb) {
+        if rect.width == 0 || rect.height == 0 {
+            return;
+        }
+        self.fill_rect(Rect::new(rect.x, rect.y, rect.width, 1), color);
+        self.fill_rect(Rect::new(rect.x, rect.bottom() - 1, rect.width, 1), color);
+        self.fill_rect(Rect::new(rect.x, rect.y, 1, rect.height), color);
+        self.fill_rect(Rect::new(rect.right() - 1, rect.y, 1, rect.height), color);
+    }
+
+    fn text(&mut self, text: &str, x: i32, y: i32, unit: u32, color: Rgb) {
+        for rect in text_rects(text, x, y, unit) {
+            self.fill_rect(rect, color);
+        }
+    }
+}
+
+//
+// A board is drawn one pixel per cell, then scaled up with blit(): the
+// well with its border, then the right panel.
+//
+pub fn board_size(columns: usize, rows: usize) -> (u32, u32) {
+    (
+        main_width(columns) + RIGHT_PANEL + BORDER,
+        BORDER + (rows as u32).max(PANEL_ROWS) + BORDER,
+    )
+}
+
+// board_size() of the message's grid, or of a default board without one.
+pub fn message_size(message: &tc::Msg) -> (u32, u32) {
+    let (columns, rows) = grid_size(message);
+    board_size(columns, rows)
+}
+
+fn grid_size(message: &tc::Msg) -> (usize, usize) {
+    match message.grid {
+        Some(ref grid) => (grid.width(), grid.height()),
+        None => (tc::COLUMNS, tc::ROWS),
+    }
+}
+
+fn main_width(columns: usize) -> u32 {
+    BORDER + columns as u32 + BORDER
+}
+
+fn board_point(x: i32, y: i32) -> (i32, i32) {
+    (x + BORDER as i32, y + BORDER as i32)
+}
+
+//
+// Paints the board of `message` at the origin of `r`, one pixel per cell:
+// the well, the falling block, the settled cells and the next block. `r`
+// should be message_size() big.
+//
+pub fn paint_board<R: Renderer>(r: &mut R, message: &tc::Msg) {
+    let (columns, rows) = grid_size(message);
+    let (board_width, board_height) = board_size(columns, rows);
+    let main_width = main_width(columns);
+
+    r.clear((38, 38, 38));
+    r.fill_rect(Rect::new(0, 0, main_width, board_height), (25, 25, 25));
+    r.fill_rect(
+        Rect::new(1, 1, columns as u32, rows as u32),
+        (75, 75, 75),
+    );
+
+    if let Some(ref block) = message.block {
+        let points: Vec<(i32, i32)> = block
+            .points_ref()
+            .iter()
+            .filter(|point| point.y() >= 0)
+            .map(|point| board_point(point.x(), point.y()))
+            .collect();
+        r.draw_points(&points, *block.color_ref());
+    }
+
+    if let Some(ref grid) = message.grid {
+        let data = grid.get_data();
+        for (r_index, row) in data.iter().enumerate() {
+            for (c_index, &piece) in row.iter().enumerate() {
+                if piece == 0 {
+                    continue;
+                }
+
+                let color = match message.scheme {
+                    Some(ref scheme) => scheme.color(&tc::BlockType::new(piece)),
+                    None => (255, 255, 255),
+                };
+                r.draw_points(&[board_point(c_index as i32, r_index as i32)], color);
+            }
+        }
+    }
+
+    if let Some(ref clearing) = message.clearing {
+        if let Some(color) = clearing_color(clearing) {
+            for &row in clearing.rows.iter() {
+                let (x, y) = board_point(0, row as i32);
+                r.fill_rect(Rect::new(x, y, columns as u32, 1), color);
+            }
+        }
+    }
+
+    if let Some(ref block) = message.block {
+        if let Some(ref next) = *block.next_ref() {
+            let points: Vec<(i32, i32)> = next.points_ref()
+                .iter()
+                .map(|point| (point.x() + 3 + main_width as i32, point.y() + 2))
+                .collect();
+
+            r.fill_rect(
+                Rect::new(
+                    main_width as i32,
+                    0,
+                    board_width - main_width,
+                    board_height,
+                ),
+                (38, 38, 38),
+            );
+            r.draw_points(&points, *next.color_ref());
+        }
+    }
+
+    // The held block goes under the next one.
+    if let (Some(hold), Some(scheme)) = (message.hold.as_ref(), message.scheme.as_ref()) {
+        let points: Vec<(i32, i32)> = hold.points()
+            .iter()
+            .map(|point| (point.x() + 3 + main_width as i32, point.y() + 5))
+            .collect();
+        r.draw_points(&points, scheme.color(hold));
+    }
+}
+
+//
+// Cleared rows flash white for the first half of the line clear delay,
+// then fade into the well. None shows the cells as they are.
+//
+fn clearing_color(clearing: &tc::LineClear) -> Option<Rgb> {
+    let progress = clearing.progress();
+    if progress < 0.5 {
+        return if (clearing.frames / 4).is_multiple_of(2) {
+            Some((255, 255, 255))
+        } else {
+            None
+        };
+    }
+
+    let fade = |from: u8, to: u8| {
+        let t = (progress - 0.5) * 2.0;
+        (from as f32 + (to as f32 - from as f32) * t) as u8
+    };
+    Some((fade(255, 75), fade(255, 75), fade(255, 75)))
+}
+
+//
+// Text is drawn after the board is scaled up into `rect`, since the board
+// itself has one pixel per cell.
+//
+pub fn paint_labels<R: Renderer>(
+    r: &mut R,
+    message: &tc::Msg,
+    worker_index: u8,
+    tetris_index: u32,
+    rect: Rect,
+) {
+    let (columns, _) = grid_size(message);
+    let (board_width, _) = board_size(columns, 0);
+    let scale = rect.width as f32 / board_width as f32;
+    let panel_width = (RIGHT_PANEL as f32 * scale) as u32;
+    // Eight characters per line.
+    let unit = panel_width / (8 * (GLYPH_WIDTH + 1));
+    if unit == 0 {
+        return;
+    }
+
+    let mut lines = vec![format!("W{} B{}", worker_index, tetris_index)];
+    match (message.stats.as_ref(), message.mode.as_ref()) {
+        // A board played for a goal shows its clock instead of the score.
+        (Some(stats), Some(mode)) => {
+            lines.push(tc::clock(stats.frames));
+            lines.push(format!("LN {}/{}", stats.lines, mode.goal.lines()));
+            lines.push(format!("PPS {:.1}", stats.pps()));
+            lines.push(format!("FLT {}", mode.faults));
+        }
+        (Some(stats), None) => {
+            lines.push("SCORE".to_string());
+            lines.push(stats.score.to_string());
+            lines.push(format!("LN {}", stats.lines));
+            lines.push(format!("LV {}", stats.level()));
+            lines.push(format!("PPS {:.1}", stats.pps()));
+        }
+        _ => (),
+    }
+
+    let x = rect.x + (main_width(columns) as f32 * scale) as i32 + unit as i32;
+    let mut y = rect.y + (7.0 * scale) as i32;
+    let line_height = ((GLYPH_HEIGHT + 2) * unit) as i32;
+    for line in lines {
+        if y + line_height > rect.bottom() {
+            break;
+        }
+        r.text(&line, x, y, unit, (200, 200, 200));
+        y += line_height;
+    }
+}
+
+//
+// `text` on a dark band across the middle of the main area of a board
+// `columns` wide.
+//
+pub fn paint_overlay<R: Renderer>(r: &mut R, text: &str, rect: Rect, columns: usize) {
+    let (board_width, _) = board_size(columns, 0);
+    let scale = rect.width as f32 / board_width as f32;
+    let main_width = (main_width(columns) as f32 * scale) as u32;
+    let unit = (main_width / (text_width(text, 1) + 2)).max(1);
+    let height = (GLYPH_HEIGHT + 2) * unit;
+    let top = rect.y + (rect.height as i32 - height as i32) / 2;
+
+    r.fill_rect(Rect::new(rect.x, top, main_width, height), (0, 0, 0));
+
+    let x = rect.x + (main_width as i32 - text_width(text, unit) as i32) / 2;
+    r.text(text, x, top + unit as i32, unit, (255, 255, 255));
+}
+
+//
+// Everything shown for one board message: the board drawn into `board`,
+// which is resized to fit the message's board, scaled into `rect`, then
+// its labels and the final time or game over overlay.
+//
+pub fn paint_message<R: Renderer>(
+    r: &mut R,
+    board: &mut Framebuffer,
+    message: &tc::Msg,
+    worker_index: u8,
+    tetris_index: u32,
+    rect: Rect,
+) {
+    let (width, height) = message_size(message);
+    if (board.width(), board.height()) != (width, height) {
+        *board = Framebuffer::new(width, height);
+    }
+    paint_board(board, message);
+    r.blit(board, rect);
+    paint_labels(r, message, worker_index, tetris_index, rect);
+
+    let record = match (message.mode.as_ref(), message.stats.as_ref()) {
+        (Some(mode), Some(stats)) => mode.record(stats),
+        _ => None,
+    };
+    if let Some(record) = record {
+        paint_overlay(r, &record.time(), rect, grid_size(message).0);
+    } else if message.game_over {
+        paint_overlay(r, "GAME OVER", rect, grid_size(message).0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs::File;
+    use std::path::PathBuf;
+
+    // A seeded game some pieces in, as the worker would first send it.
+    fn message() -> tc::Msg {
+        let mut tetris = tc::Tetris::with_seed(7);
+        tetris.init();
+        for frame in 0..600 {
+            if frame % 37 == 0 {
+                let side = if frame % 74 == 0 {
+                    tc::BlockEvent::Left
+                } else {
+                    tc::BlockEvent::Right
+                };
+                tetris.event(Some(vec![side.clone(), side, tc::BlockEvent::Drop]));
+            }
+            tetris.tick();
+        }
+        assert!(!tetris.is_game_over());
+
+        let mut encoder = tc::DeltaEncoder::new();
+        encoder.message(tc::AppEvent::Tick(0, 0), &tetris)
+    }
+
+    //
+    // Compares against tests/golden/<name>.ppm. Run with UPDATE_GOLDEN=1 to
+    // write the images instead, after checking the change is intended.
+    //
+    fn golden(name: &str, framebuffer: &Framebuffer) {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/golden");
+        path.push(format!("{}.ppm", name));
+
+        if env::var_os("UPDATE_GOLDEN").is_some() {
+            framebuffer.write_ppm(&mut File::create(&path).unwrap()).unwrap();
+            return;
+        }
+
+        let expected = Framebuffer::read_ppm(&mut File::open(&path).unwrap()).unwrap();
+        assert!(
+            expected == *framebuffer,
+            "{} differs from {}",
+            name,
+            path.display()
+        );
+    }
+
+    #[test]
+    fn board() {
+        let mut board = Framebuffer::new(BOARD_WIDTH, BOARD_HEIGHT);
+        paint_board(&mut board, &message());
+        golden("board", &board);
+    }
+
+    #[test]
+    fn scaled_with_labels() {
+        let mut board = Framebuffer::new(BOARD_WIDTH, BOARD_HEIGHT);
+        let mut target = Framebuffer::new(BOARD_WIDTH * 5, BOARD_HEIGHT * 5);
+        let rect = Rect::new(0, 0, target.width(), target.height());
+        paint_message(&mut target, &mut board, &message(), 0, 1, rect);
+        golden("message", &target);
+    }
+
+    #[test]
+    fn cleared_rows_flash_then_fade() {
+        let mut message = message();
+        let mut clearing = tc::LineClear {
+            rows: vec![19],
+            frames: 0,
+            delay: 20,
+        };
+        let row_color = |message: &tc::Msg| {
+            let mut board = Framebuffer::new(BOARD_WIDTH, BOARD_HEIGHT);
+            paint_board(&mut board, message);
+            board.pixel(BORDER, BORDER + 19).unwrap()
+        };
+
+        message.clearing = Some(clearing.clone());
+        assert_eq!(row_color(&message), (255, 255, 255));
+
+        clearing.frames = 15;
+        message.clearing = Some(clearing.clone());
+        let (r, g, b) = row_color(&message);
+        assert!(r < 255 && r > 75 && r == g && g == b);
+    }
+
+    #[test]
+    fn game_over() {
+        let mut message = message();
+        message.game_over = true;
+
+        let mut board = Framebuffer::new(BOARD_WIDTH, BOARD_HEIGHT);
+        let mut target = Framebuffer::new(BOARD_WIDTH * 5, BOARD_HEIGHT * 5);
+        let rect = Rect::new(0, 0, target.width(), target.height());
+        paint_message(&mut target, &mut board, &message, 0, 1, rect);
+
+        // The band is black across the main area, the panel is untouched.
+        let middle = target.height() / 2;
+        assert_eq!(target.pixel(0, middle), Some((0, 0, 0)));
+        assert_eq!(target.pixel(target.width() - 1, middle), Some((38, 38, 38)));
+    }
+
+    #[test]
+    fn sprint_clock() {
+        let mut message = message();
+        message.mode = Some(tc::ModeState {
+            goal: tc::Goal::Lines(40),
+            faults: 0,
+            finished: false,
+        });
+
+        let mut board = Framebuffer::new(BOARD_WIDTH, BOARD_HEIGHT);
+        let mut target = Framebuffer::new(BOARD_WIDTH * 5, BOARD_HEIGHT * 5);
+        let rect = Rect::new(0, 0, target.width(), target.height());
+        let middle = target.height() / 2;
+        paint_message(&mut target, &mut board, &message, 0, 1, rect);
+        assert_ne!(target.pixel(0, middle), Some((0, 0, 0)));
+
+        // The final time goes across the board once the goal is reached.
+        message.mode.as_mut().unwrap().finished = true;
+        paint_message(&mut target, &mut board, &message, 0, 1, rect);
+        assert_eq!(target.pixel(0, middle), Some((0, 0, 0)));
+    }
+}
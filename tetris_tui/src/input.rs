@@ -0,0 +1,129 @@
+use tc;
+
+use serde_json;
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::io::{self, Read};
+
+pub use tc::MAX_PLAYERS;
+
+//
+// Key names to block events, one table per player. The names are the SDL
+// ones, see term::key_names.
+//
+pub struct KeyMap {
+    bindings: Vec<(String, tc::BlockEvent)>,
+}
+
+impl KeyMap {
+    pub fn new(bindings: Vec<(&str, tc::BlockEvent)>) -> KeyMap {
+        KeyMap {
+            bindings: bindings
+                .into_iter()
+                .map(|(name, event)| (name.to_string(), event))
+                .collect(),
+        }
+    }
+
+    pub fn from_names(bindings: &HashMap<String, tc::BlockEvent>) -> KeyMap {
+        KeyMap {
+            bindings: bindings
+                .iter()
+                .map(|(name, event)| (name.clone(), event.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn event(&self, name: &str) -> Option<tc::BlockEvent> {
+        self.bindings
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, event)| event.clone())
+    }
+
+    //
+    // The shared layouts, with the digits in place of the keypad, which
+    // terminals do not tell apart.
+    //
+    pub fn preset(player: usize) -> KeyMap {
+        let bindings = tc::preset_keys(player)
+            .into_iter()
+            .map(|(name, event)| (name.trim_start_matches("Keypad "), event))
+            .collect();
+        KeyMap::new(bindings)
+    }
+}
+
+//
+// The `players` part of the SDL front-end's settings file, so the same
+// file can be used here. The other settings are ignored: terminals only
+// report key presses, so held keys repeat at the terminal's own rate.
+//
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub players: Vec<HashMap<String, tc::BlockEvent>>,
+}
+
+impl Config {
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Config, ConfigError> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    pub fn key_maps(&self, count: usize) -> Vec<KeyMap> {
+        (0..count.min(MAX_PLAYERS))
+            .map(|player| match self.players.get(player) {
+                Some(bindings) => KeyMap::from_names(bindings),
+                None => KeyMap::preset(player),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Io(ref e) => write!(f, "config io error: {}", e),
+            ConfigError::Json(ref e) => write!(f, "config error: {}", e),
+        }
+    }
+}
+
+impl error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> ConfigError {
+        ConfigError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(e: serde_json::Error) -> ConfigError {
+        ConfigError::Json(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_over_presets() {
+        let json = r#"{ "handling": { "das": 8 }, "players": [{ "X": "Rotate" }] }"#;
+        let config = Config::read_from(&mut json.as_bytes()).unwrap();
+        let maps = config.key_maps(2);
+
+        assert_eq!(maps[0].event("X"), Some(tc::BlockEvent::Rotate));
+        assert_eq!(maps[0].event("Up"), None);
+        assert_eq!(maps[1].event("A"), Some(tc::BlockEvent::Left));
+        assert_eq!(KeyMap::preset(3).event("8"), Some(tc::BlockEvent::Rotate));
+    }
+}
@@ -0,0 +1,193 @@
+//
+// Plays or watches games in a terminal, no SDL or browser needed:
+//
+//     tetris_tui [--boards <n>] [--players <n>] [--config <file>]
+//                [--rules <classic|guideline|nes|tgm>] [--size <columns>x<rows>]
+//     tetris_tui --replay <file>
+//
+// Keys are the SDL front-end's, P pauses and Escape or Ctrl+C quits.
+//
+extern crate libc;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate tetris_core as tc;
+extern crate tetris_render as tr;
+
+mod input;
+mod screen;
+mod term;
+
+use input::{Config, KeyMap};
+use screen::Board;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+fn arg(name: &str) -> Option<String> {
+    env::args().skip_while(|arg| arg != name).nth(1)
+}
+
+fn number_arg(name: &str, default: usize) -> usize {
+    arg(name).and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+fn config_arg() -> Config {
+    match arg("--config") {
+        Some(path) => {
+            let mut file = File::open(&path).expect("Can not open the config file!");
+            Config::read_from(&mut file).expect("Can not read the config file!")
+        }
+        None => Config::default(),
+    }
+}
+
+fn rules_arg() -> tc::Preset {
+    match arg("--rules") {
+        Some(name) => tc::Preset::from_name(&name).expect("Unknown rules!"),
+        None => tc::Preset::Classic,
+    }
+}
+
+fn size_arg() -> tc::Grid {
+    let size = match arg("--size") {
+        Some(size) => size,
+        None => return tc::Grid::new(),
+    };
+    let mut numbers = size.split('x').map(|n| n.parse().expect("Not a board size!"));
+    match (numbers.next(), numbers.next()) {
+        (Some(columns), Some(rows)) => match tc::Grid::with_size(columns, rows, tc::BUFFER_ROWS) {
+            Ok(grid) => grid,
+            Err(e) => panic!("Can not use the board size: {}", e),
+        },
+        _ => panic!("Not a board size!"),
+    }
+}
+
+fn replay_arg() -> Option<tc::Replay> {
+    let path = arg("--replay")?;
+    let mut file = File::open(&path).expect("Can not open the replay file!");
+    Some(tc::Replay::read_from(&mut file).expect("Can not read the replay file!"))
+}
+
+//
+// Local games, the first ones played with the players' keys and the others
+// falling on their own.
+//
+struct Local {
+    games: Vec<tc::Tetris>,
+    keys: Vec<KeyMap>,
+}
+
+impl Local {
+    fn new(boards: usize, keys: Vec<KeyMap>, preset: tc::Preset, grid: tc::Grid) -> Local {
+        let mut ruleset = tc::Ruleset::preset(preset);
+        if preset == tc::Preset::Classic {
+            ruleset.line_clear_delay = tc::LINE_CLEAR_DELAY;
+        }
+
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let games = (0..boards.max(keys.len()))
+            .map(|index| {
+                let mut tetris = tc::Tetris::with_ruleset(seed + index as u64, ruleset.clone());
+                tetris.grid = grid.clone();
+                tetris.init();
+                tetris
+            })
+            .collect();
+        Local {
+            games: games,
+            keys: keys,
+        }
+    }
+
+    fn key(&mut self, name: &str) {
+        for (keys, tetris) in self.keys.iter().zip(self.games.iter_mut()) {
+            if let Some(event) = keys.event(name) {
+                tetris.event(Some(vec![event]));
+            }
+        }
+    }
+
+    fn tick(&mut self) {
+        for tetris in self.games.iter_mut() {
+            tetris.tick();
+        }
+    }
+
+    fn boards(&self, paused: bool) -> Vec<Board> {
+        self.games
+            .iter()
+            .enumerate()
+            .map(|(index, tetris)| Board {
+                message: tr::state_message(tetris),
+                title: if index < self.keys.len() {
+                    ["P1", "P2", "P3", "P4"][index]
+                } else {
+                    "B"
+                },
+                paused: paused,
+            })
+            .collect()
+    }
+}
+
+fn main() {
+    let mut replay =
+        replay_arg().map(|replay| tc::ReplayPlayer::new(replay).expect("Can not play the replay file!"));
+    let mut local = Local::new(
+        number_arg("--boards", 1),
+        config_arg().key_maps(number_arg("--players", 1)),
+        rules_arg(),
+        size_arg(),
+    );
+
+    let _raw_mode = term::RawMode::enter().expect("Not a terminal!");
+    let frame = Duration::from_secs(1) / tc::FRAMES_PER_SECOND;
+    let mut paused = false;
+
+    'game: loop {
+        let start = Instant::now();
+
+        for key in term::read_keys() {
+            match key.as_str() {
+                "Escape" | "Ctrl+C" => break 'game,
+                "P" => paused = !paused,
+                name if !paused && replay.is_none() => local.key(name),
+                _ => (),
+            }
+        }
+
+        let boards = match replay {
+            Some(ref mut player) => {
+                if !paused {
+                    player.step();
+                }
+                vec![Board {
+                    message: tr::state_message(player.tetris()),
+                    title: "REPLAY",
+                    paused: paused,
+                }]
+            }
+            None => {
+                if !paused {
+                    local.tick();
+                }
+                local.boards(paused)
+            }
+        };
+
+        let mut stdout = io::stdout();
+        stdout.write_all(screen::draw(&boards, term::width()).as_bytes()).unwrap();
+        stdout.flush().unwrap();
+
+        let elapsed = start.elapsed();
+        if elapsed < frame {
+            thread::sleep(frame - elapsed);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use tc;
+use tr;
+
+use std::fmt::Write;
+
+// Room for the stats under each board.
+const LABEL_LINES: u32 = 3;
+const GAP: u32 = 2;
+
+pub struct Board {
+    pub message: tc::Msg,
+    pub title: &'static str,
+    pub paused: bool,
+}
+
+//
+// Draws the boards left to right, wrapping at `width` columns, with their
+// stats underneath. Boards are painted with tetris_render into a
+// framebuffer and every two pixel rows become one line of upper half
+// blocks, the top pixel in the foreground colour and the bottom one in the
+// background colour. So a board is one character per cell across and two
+// cells per line. All boards are the size of the first.
+//
+pub fn draw(boards: &[Board], width: u32) -> String {
+    let (board_columns, board_rows) = match boards.first() {
+        Some(board) => tr::message_size(&board.message),
+        None => (tr::BOARD_WIDTH, tr::BOARD_HEIGHT),
+    };
+    let per_line = ((width + GAP) / (board_columns + GAP)).max(1) as usize;
+
+    let mut out = String::from("\x1b[H");
+    let mut framebuffer = tr::Framebuffer::new(board_columns, board_rows);
+    for row in boards.chunks(per_line) {
+        let framebuffers: Vec<tr::Framebuffer> = row.iter()
+            .map(|board| {
+                tr::paint_board(&mut framebuffer, &board.message);
+                framebuffer.clone()
+            })
+            .collect();
+
+        for y in (0..board_rows).step_by(2) {
+            for framebuffer in framebuffers.iter() {
+                let mut last = None;
+                for x in 0..board_columns {
+                    let top = framebuffer.pixel(x, y).unwrap_or((0, 0, 0));
+                    let bottom = framebuffer.pixel(x, y + 1).unwrap_or((0, 0, 0));
+                    if last != Some((top, bottom)) {
+                        write!(
+                            out,
+                            "\x1b[38;2;{};{};{};48;2;{};{};{}m",
+                            top.0, top.1, top.2, bottom.0, bottom.1, bottom.2
+                        ).unwrap();
+                        last = Some((top, bottom));
+                    }
+                    out.push('\u{2580}');
+                }
+                write!(out, "\x1b[0m{:1$}", "", GAP as usize).unwrap();
+            }
+            out.push_str("\x1b[K\r\n");
+        }
+
+        for line in 0..LABEL_LINES {
+            for board in row {
+                let text = label(board, line);
+                let text: String = text.chars().take(board_columns as usize).collect();
+                write!(out, "{:1$}", text, (board_columns + GAP) as usize).unwrap();
+            }
+            out.push_str("\x1b[K\r\n");
+        }
+    }
+    out.push_str("\x1b[J");
+    out
+}
+
+fn label(board: &Board, line: u32) -> String {
+    let stats = board.message.stats.clone().unwrap_or_default();
+    match line {
+        0 => format!("{} SCORE {}", board.title, stats.score),
+        1 => format!("LN {} LV {} PPS {:.1}", stats.lines, stats.level(), stats.pps()),
+        _ if board.message.game_over => "GAME OVER".to_string(),
+        _ if board.paused => "PAUSED".to_string(),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(title: &'static str) -> Board {
+        let mut tetris = tc::Tetris::with_seed(1);
+        tetris.init();
+        Board {
+            message: tr::state_message(&tetris),
+            title: title,
+            paused: false,
+        }
+    }
+
+    fn lines(screen: &str) -> usize {
+        screen.matches("\r\n").count()
+    }
+
+    #[test]
+    fn wraps_to_the_width() {
+        let height = (tr::BOARD_HEIGHT / 2 + LABEL_LINES) as usize;
+        let boards = vec![board("P1"), board("P2"), board("B3")];
+
+        assert_eq!(lines(&draw(&boards, 80)), height);
+        assert_eq!(lines(&draw(&boards, 40)), height * 3);
+
+        let screen = draw(&boards, 80);
+        assert!(screen.contains("P1 SCORE 0"));
+        assert!(screen.contains("B3 SCORE 0"));
+    }
+}
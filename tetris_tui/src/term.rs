@@ -0,0 +1,120 @@
+use libc;
+
+use std::io::{self, Read, Write};
+use std::mem;
+
+//
+// Puts the terminal in raw mode on the alternate screen with the cursor
+// hidden, and puts everything back when dropped. Reads do not wait, so
+// the game loop can poll for keys once per frame.
+//
+pub struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    pub fn enter() -> io::Result<RawMode> {
+        let mut original: libc::termios = unsafe { mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut raw = original;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        raw.c_cc[libc::VMIN] = 0;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut stdout = io::stdout();
+        stdout.write_all(b"\x1b[?1049h\x1b[?25l\x1b[2J")?;
+        stdout.flush()?;
+        Ok(RawMode { original: original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(b"\x1b[0m\x1b[?25h\x1b[?1049l");
+        let _ = stdout.flush();
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
+
+// Columns of the terminal, 80 when it can not be asked.
+pub fn width() -> u32 {
+    let mut size: libc::winsize = unsafe { mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    if ok && size.ws_col > 0 {
+        size.ws_col as u32
+    } else {
+        80
+    }
+}
+
+// Names of the keys pressed since the last call.
+pub fn read_keys() -> Vec<String> {
+    let mut buffer = [0; 64];
+    match io::stdin().read(&mut buffer) {
+        Ok(size) => key_names(&buffer[..size]),
+        Err(_) => Vec::new(),
+    }
+}
+
+//
+// Turns terminal input into key names, the same names SDL uses so key
+// binding files work in both front-ends: "Left", "Space", "Z"... Letters
+// are named in upper case with or without shift.
+//
+pub fn key_names(mut bytes: &[u8]) -> Vec<String> {
+    let mut keys = Vec::new();
+    while !bytes.is_empty() {
+        let (name, size) = match bytes {
+            [0x1b, b'[', b'A', ..] | [0x1b, b'O', b'A', ..] => ("Up".to_string(), 3),
+            [0x1b, b'[', b'B', ..] | [0x1b, b'O', b'B', ..] => ("Down".to_string(), 3),
+            [0x1b, b'[', b'C', ..] | [0x1b, b'O', b'C', ..] => ("Right".to_string(), 3),
+            [0x1b, b'[', b'D', ..] | [0x1b, b'O', b'D', ..] => ("Left".to_string(), 3),
+            // Other escape sequences are skipped whole.
+            [0x1b, b'[', ..] => {
+                let end = bytes[2..]
+                    .iter()
+                    .position(|byte| (0x40..0x7f).contains(byte))
+                    .map_or(bytes.len(), |end| end + 3);
+                bytes = &bytes[end..];
+                continue;
+            }
+            [0x1b, ..] => ("Escape".to_string(), 1),
+            [0x03, ..] => ("Ctrl+C".to_string(), 1),
+            [b'\t', ..] => ("Tab".to_string(), 1),
+            [b'\r', ..] | [b'\n', ..] => ("Return".to_string(), 1),
+            [0x7f, ..] | [0x08, ..] => ("Backspace".to_string(), 1),
+            [b' ', ..] => ("Space".to_string(), 1),
+            [byte, ..] if byte.is_ascii_graphic() => {
+                ((*byte as char).to_ascii_uppercase().to_string(), 1)
+            }
+            _ => {
+                bytes = &bytes[1..];
+                continue;
+            }
+        };
+        keys.push(name);
+        bytes = &bytes[size..];
+    }
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(
+            key_names(b"\x1b[Dz \x1b[1;5Cq\x1b"),
+            vec!["Left", "Z", "Space", "Q", "Escape"]
+        );
+        assert_eq!(key_names(b"\x1bOA\x03"), vec!["Up", "Ctrl+C"]);
+    }
+}
@@ -14,7 +14,6 @@ pub use wasm32::on;
 mod wasm32 {
     use super::*;
 
-    use std::ffi::CString;
     use std::mem;
     use std::os::raw::{c_char, c_int};
     use std::sync::Mutex;
@@ -22,9 +21,25 @@ mod wasm32 {
 
     use tetris_core::*;
 
+    struct Board {
+        mode: Mode,
+        encoder: DeltaEncoder,
+    }
+
+    impl Board {
+        // The next message of the board, with its mode.
+        fn message(&mut self, event: AppEvent) -> Msg {
+            let mut msg = self.encoder.message(event, &self.mode.tetris);
+            msg.mode = self.mode.state();
+            msg
+        }
+    }
+
     lazy_static! {
-        static ref TETRIS: Mutex<Vec<Tetris>> = Mutex::new(vec![]);
+        static ref TETRIS: Mutex<Vec<Board>> = Mutex::new(vec![]);
         static ref IDX: Mutex<Option<u8>> = Mutex::new(None);
+        // Replies use the encoding of the request being handled.
+        static ref CODEC: Mutex<Codec> = Mutex::new(Codec::Binary);
     }
 
     #[allow(dead_code)]
@@ -46,14 +61,11 @@ mod wasm32 {
         }
     }
 
-    fn send_back(msg: Msg) {
-        let json = serde_json::to_string(&msg).expect("[core] Serialze error\0");
-        let send_back = CString::new(json).unwrap();
-        let send_back = send_back.into_raw();
-        let len = unsafe { libc::strlen(send_back) as i32 };
+    fn send_back(msgs: Vec<Msg>) {
+        let mut send_back = CODEC.lock().unwrap().encode_msgs(&msgs);
 
         unsafe {
-            asm::emscripten_worker_respond(send_back, len + 1);
+            asm::emscripten_worker_respond(send_back.as_mut_ptr() as *mut c_char, send_back.len() as c_int);
         }
     }
 
@@ -61,91 +73,202 @@ mod wasm32 {
         unsafe { mem::transmute(slice::from_raw_parts(data, size as usize)) }
     }
 
-    fn worker_guard(worker_id: u8) -> bool {
+    fn check_worker(worker_id: u8) -> Result<(), WorkerError> {
         match *IDX.lock().unwrap() {
-            Some(ref idx) => worker_id.ne(idx),
-            None => true,
+            Some(idx) if idx == worker_id => Ok(()),
+            Some(_) => Err(WorkerError::WrongWorker),
+            None => Err(WorkerError::NotInitialized),
         }
     }
 
-    fn init_worker(worker_index: u8, tetris_count: u32) {
-        if let Some(idx) = *IDX.lock().unwrap() {
-            log::error(format!("already initialized: {}", idx));
-            return;
+    //
+    // Runs `f` on one board and answers with its message.
+    //
+    fn on_board<F>(worker_index: u8, tetris_index: u32, f: F) -> Result<Vec<Msg>, WorkerError>
+    where
+        F: FnOnce(&mut Board) -> Msg,
+    {
+        check_worker(worker_index)?;
+
+        match TETRIS.lock().unwrap().get_mut(tetris_index as usize) {
+            Some(board) => Ok(vec![f(board)]),
+            None => Err(WorkerError::NoSuchBoard),
+        }
+    }
+
+    //
+    // Runs `f` on every board and answers with one message list.
+    //
+    fn on_all<F>(worker_index: u8, mut f: F) -> Result<Vec<Msg>, WorkerError>
+    where
+        F: FnMut(u32, &mut Board) -> Msg,
+    {
+        check_worker(worker_index)?;
+
+        Ok(TETRIS
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .enumerate()
+            .map(|(tetris_index, board)| f(tetris_index as u32, board))
+            .collect())
+    }
+
+    fn init_worker(worker_index: u8, tetris_count: u32) -> Result<Vec<Msg>, WorkerError> {
+        if IDX.lock().unwrap().is_some() {
+            return Err(WorkerError::AlreadyInitialized);
         }
 
         *IDX.lock().unwrap() = Some(worker_index);
 
         for _ in 0..tetris_count {
-            TETRIS.lock().unwrap().push(Tetris::new());
+            TETRIS.lock().unwrap().push(Board {
+                mode: Mode::new(Tetris::new(), Goal::Endless),
+                encoder: DeltaEncoder::new(),
+            });
         }
 
         let event = AppEvent::InitWorker(worker_index, tetris_count);
-        send_back(Msg::new(event, None, None, None));
+        Ok(vec![Msg::new(event, None, None, None)])
     }
 
-    fn init_tetris(worker_index: u8, tetris_index: u32) {
-        if worker_guard(worker_index) {
-            return;
-        }
-
-        let ref mut tetris = TETRIS.lock().unwrap()[tetris_index as usize];
-        tetris.init();
+    //
+    // Starts the board over with the preset's rules and the goal, keeping
+    // its seed. Classic gets the line clear delay the front-ends animate.
+    //
+    fn init_tetris(
+        worker_index: u8,
+        tetris_index: u32,
+        preset: Preset,
+        goal: Goal,
+    ) -> Result<Vec<Msg>, WorkerError> {
+        on_board(worker_index, tetris_index, |board| {
+            let mut ruleset = Ruleset::preset(preset);
+            if preset == Preset::Classic {
+                ruleset.line_clear_delay = LINE_CLEAR_DELAY;
+            }
+            let mut tetris = Tetris::with_ruleset(board.mode.tetris.seed, ruleset);
+            tetris.init();
+            board.mode = Mode::new(tetris, goal);
+            board.encoder.reset();
+            board.message(AppEvent::InitTetris(worker_index, tetris_index, preset, goal))
+        })
+    }
 
-        send_back(Msg::new(
-            AppEvent::InitTetris(worker_index, tetris_index),
-            Some(tetris.get_block()),
-            None,
-            Some(tetris.scheme.clone()),
-        ));
+    fn tick_event(worker_index: u8, tetris_index: u32) -> Result<Vec<Msg>, WorkerError> {
+        on_board(worker_index, tetris_index, |board| {
+            board.mode.tick();
+            board.message(AppEvent::Tick(worker_index, tetris_index))
+        })
     }
 
-    fn tick_event(worker_index: u8, tetris_index: u32) {
-        if worker_guard(worker_index) {
-            return;
-        }
+    fn user_event(
+        worker_index: u8,
+        tetris_index: u32,
+        block_events: Option<Vec<BlockEvent>>,
+    ) -> Result<Vec<Msg>, WorkerError> {
+        on_board(worker_index, tetris_index, |board| {
+            board.mode.event(block_events);
+            board.message(AppEvent::User(worker_index, tetris_index, None))
+        })
+    }
 
-        let ref mut tetris = TETRIS.lock().unwrap()[tetris_index as usize];
-        tetris.tick();
+    fn tick_all(worker_index: u8) -> Result<Vec<Msg>, WorkerError> {
+        on_all(worker_index, |tetris_index, board| {
+            board.mode.tick();
+            board.message(AppEvent::Tick(worker_index, tetris_index))
+        })
+    }
 
-        send_back(Msg::new(
-            AppEvent::Tick(worker_index, tetris_index),
-            Some(tetris.get_block()),
-            Some(tetris.get_grid()),
-            Some(tetris.scheme.clone()),
-        ));
+    fn user_all(worker_index: u8, block_events: Option<Vec<BlockEvent>>) -> Result<Vec<Msg>, WorkerError> {
+        on_all(worker_index, |tetris_index, board| {
+            board.mode.event(block_events.clone());
+            board.message(AppEvent::User(worker_index, tetris_index, None))
+        })
     }
 
-    fn user_event(worker_index: u8, tetris_index: u32, block_events: Option<Vec<BlockEvent>>) {
-        if worker_guard(worker_index) {
-            return;
+    //
+    // Runs each board's own input and answers with one message list. An
+    // unknown board fails the whole batch before any board moves.
+    //
+    fn user_each(worker_index: u8, inputs: Vec<(u32, Vec<BlockEvent>)>) -> Result<Vec<Msg>, WorkerError> {
+        check_worker(worker_index)?;
+
+        let mut boards = TETRIS.lock().unwrap();
+        if inputs.iter().any(|&(tetris_index, _)| tetris_index as usize >= boards.len()) {
+            return Err(WorkerError::NoSuchBoard);
         }
 
-        let ref mut tetris = TETRIS.lock().unwrap()[tetris_index as usize];
-        tetris.event(block_events);
+        Ok(inputs
+            .into_iter()
+            .map(|(tetris_index, block_events)| {
+                let board = &mut boards[tetris_index as usize];
+                board.mode.event(Some(block_events));
+                board.message(AppEvent::User(worker_index, tetris_index, None))
+            })
+            .collect())
+    }
 
-        send_back(Msg::new(
-            AppEvent::User(worker_index, tetris_index, None),
-            Some(tetris.get_block()),
-            Some(tetris.get_grid()),
-            Some(tetris.scheme.clone()),
-        ));
+    fn resync(worker_index: u8, tetris_index: u32) -> Result<Vec<Msg>, WorkerError> {
+        on_board(worker_index, tetris_index, |board| {
+            board.encoder.reset();
+            board.message(AppEvent::Resync(worker_index, tetris_index))
+        })
     }
 
-    #[no_mangle]
-    pub fn on(data: *mut c_char, size: c_int) {
-        let app_events = String::from_utf8(into_raw(data, size).to_vec()).unwrap();
-        match serde_json::from_str::<AppEvent>(app_events.as_str()).unwrap() {
+    fn handle(event: AppEvent) -> Result<Vec<Msg>, WorkerError> {
+        match event {
             AppEvent::InitWorker(worker_index, tetris_count) => {
                 init_worker(worker_index, tetris_count)
             }
-            AppEvent::InitTetris(worker_index, tetris_index) => {
-                init_tetris(worker_index, tetris_index)
+            AppEvent::InitTetris(worker_index, tetris_index, preset, goal) => {
+                init_tetris(worker_index, tetris_index, preset, goal)
             }
             AppEvent::Tick(worker_index, tetris_index) => tick_event(worker_index, tetris_index),
             AppEvent::User(worker_index, tetris_index, block_event) => {
                 user_event(worker_index, tetris_index, block_event)
             }
+            AppEvent::Resync(worker_index, tetris_index) => resync(worker_index, tetris_index),
+            AppEvent::TickAll(worker_index) => tick_all(worker_index),
+            AppEvent::UserAll(worker_index, block_events) => user_all(worker_index, block_events),
+            AppEvent::UserEach(worker_index, inputs) => user_each(worker_index, inputs),
+            // Only workers send errors.
+            AppEvent::Error(..) => Err(WorkerError::Decode),
+        }
+    }
+
+    fn send_error(error: WorkerError, event: Option<AppEvent>) {
+        log::error(format!("{}: {:?}", error, event));
+
+        let worker_index = match (*IDX.lock().unwrap(), &event) {
+            (Some(idx), _) => idx,
+            (None, &Some(ref event)) => event.worker_id(),
+            (None, &None) => 0,
+        };
+        let event = AppEvent::Error(worker_index, error, event.map(Box::new));
+        send_back(vec![Msg::new(event, None, None, None)]);
+    }
+
+    #[no_mangle]
+    pub fn on(data: *mut c_char, size: c_int) {
+        let data = into_raw(data, size);
+        *CODEC.lock().unwrap() = Codec::of(data);
+
+        let event = match decode_event(data) {
+            Ok(event) => event,
+            Err(e) => {
+                log::error(format!("bad event: {}", e));
+                return send_error(WorkerError::Decode, None);
+            }
+        };
+
+        match handle(event.clone()) {
+            Ok(msgs) => send_back(msgs),
+            // An error is not sent back inside another one.
+            Err(error) => match event {
+                AppEvent::Error(..) => send_error(error, None),
+                event => send_error(error, Some(event)),
+            },
         }
     }
 }